@@ -0,0 +1,158 @@
+use glam::Quat;
+use glam::Vec3;
+
+use super::skeleton::Pose;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    //Holds each key until the next one
+    Step,
+    //Lerp for translation and scale, slerp for rotation
+    #[default]
+    Linear
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Keyframes {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>)
+}
+
+impl Keyframes {
+    pub fn len(&self) -> usize {
+        match self {
+            Keyframes::Translation(keys) | Keyframes::Scale(keys) => keys.len(),
+            Keyframes::Rotation(keys) => keys.len()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+}
+
+//Animates one property of one joint, times are in seconds and increasing
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub joint: usize,
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes,
+    pub interpolation: Interpolation
+}
+
+impl Channel {
+
+    //Keys on either side of time and how far between them, before the first or after the last key it holds that key
+    fn locate(&self, time: f32) -> (usize, usize, f32) {
+        let next = self.times.partition_point(|key| *key <= time);
+
+        if next == 0 { return (0, 0, 0.0); }
+        if next == self.times.len() { return (next - 1, next - 1, 0.0); }
+
+        let previous = next - 1;
+        let t = (time - self.times[previous]) / (self.times[next] - self.times[previous]);
+        match self.interpolation {
+            Interpolation::Step => (previous, previous, 0.0),
+            Interpolation::Linear => (previous, next, t)
+        }
+    }
+
+    pub fn sample(&self, time: f32, pose: &mut Pose) {
+        debug_assert_eq!(self.times.len(), self.keyframes.len());
+        if self.times.is_empty() { return; }
+
+        let (a, b, t) = self.locate(time);
+        let local = &mut pose.locals[self.joint];
+
+        match &self.keyframes {
+            Keyframes::Translation(keys) => local.translation = keys[a].lerp(keys[b], t),
+            Keyframes::Rotation(keys) => local.rotation = keys[a].slerp(keys[b], t).normalize(),
+            Keyframes::Scale(keys) => local.scale = keys[a].lerp(keys[b], t)
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AnimationClip {
+    pub name: String,
+    pub channels: Vec<Channel>,
+    //Wraps time around the duration instead of holding the last keys
+    pub looping: bool
+}
+
+impl AnimationClip {
+
+    //Time of the last key of any channel
+    pub fn duration(&self) -> f32 {
+        self.channels.iter().filter_map(|channel| channel.times.last()).fold(0.0, |a, b| a.max(*b))
+    }
+
+    //Only the joint properties the clip animates are written, the rest keep what the pose had,
+    //so sampling onto the rest pose gives the whole pose
+    pub fn sample(&self, time: f32, pose: &mut Pose) {
+        let duration = self.duration();
+        let time = if self.looping && duration > 0.0 { time.rem_euclid(duration) } else { time };
+
+        for channel in &self.channels {
+            channel.sample(time, pose);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Quat, Vec3};
+
+    use super::{AnimationClip, Channel, Interpolation, Keyframes};
+    use crate::animation::Pose;
+    use crate::scene::Transform;
+
+    fn clip(interpolation: Interpolation, looping: bool) -> AnimationClip {
+        AnimationClip {
+            name: "wave".to_string(),
+            channels: vec![
+                Channel { joint: 0, times: vec![0.0, 2.0], keyframes: Keyframes::Translation(vec![Vec3::ZERO, Vec3::X * 4.0]), interpolation },
+                Channel {
+                    joint: 1,
+                    times: vec![1.0, 3.0],
+                    keyframes: Keyframes::Rotation(vec![Quat::IDENTITY, Quat::from_rotation_y(std::f32::consts::FRAC_PI_2)]),
+                    interpolation
+                }
+            ],
+            looping
+        }
+    }
+
+    fn pose() -> Pose {
+        Pose { locals: vec![Transform::from_scale(Vec3::splat(2.0)); 2] }
+    }
+
+    #[test]
+    fn linear_keys_lerp_and_slerp() {
+        let clip = clip(Interpolation::Linear, false);
+        assert_eq!(clip.duration(), 3.0);
+
+        let mut pose = pose();
+        clip.sample(2.0, &mut pose);
+        assert!(pose.locals[0].translation.abs_diff_eq(Vec3::X * 4.0, 1e-6));
+        assert!(pose.locals[1].rotation.abs_diff_eq(Quat::from_rotation_y(std::f32::consts::FRAC_PI_4), 1e-6));
+        //Not animated
+        assert_eq!(pose.locals[0].scale, Vec3::splat(2.0));
+
+        //Before the first key and after the last one the ends hold
+        clip.sample(0.5, &mut pose);
+        assert_eq!(pose.locals[1].rotation, Quat::IDENTITY);
+        clip.sample(10.0, &mut pose);
+        assert!(pose.locals[0].translation.abs_diff_eq(Vec3::X * 4.0, 1e-6));
+    }
+
+    #[test]
+    fn step_keys_hold_and_looping_wraps() {
+        let mut pose = pose();
+        clip(Interpolation::Step, false).sample(1.9, &mut pose);
+        assert_eq!(pose.locals[0].translation, Vec3::ZERO);
+
+        clip(Interpolation::Linear, true).sample(4.0, &mut pose);
+        assert!(pose.locals[0].translation.abs_diff_eq(Vec3::X * 2.0, 1e-6));
+        assert_eq!(pose.locals[1].rotation, Quat::IDENTITY);
+    }
+}
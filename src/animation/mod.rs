@@ -0,0 +1,132 @@
+//Skeletons, poses and keyframed clips for linear blend skinning.
+//Everything is plain data laid out like glTF skins and animations so an importer can fill it in directly,
+//there's no importer here since parsing glTF would need another dependency
+
+pub mod clip;
+pub mod skeleton;
+
+pub use clip::{AnimationClip, Channel, Interpolation, Keyframes};
+pub use skeleton::{Joint, Pose, Skeleton};
+
+use glam::Mat3;
+use glam::Mat4;
+use glam::Vec3;
+use glam::Vec4;
+
+use crate::renderer::data::VertexInput;
+
+//Joints that can move a single vertex
+pub const MAX_INFLUENCES: usize = 4;
+
+//Weighted sum of the joint matrices of a vertex. Weights are normalised, a vertex without any stays in place.
+//Panics when a weighted joint is outside joint_matrices
+pub fn skin_matrix(joints: [u16; MAX_INFLUENCES], weights: Vec4, joint_matrices: &[Mat4]) -> Mat4 {
+
+    let total = weights.dot(Vec4::ONE);
+    if total <= 0.0 { return Mat4::IDENTITY; }
+
+    joints.iter().zip(weights.to_array())
+        .filter(|(_, weight)| *weight > 0.0)
+        .fold(Mat4::ZERO, |sum, (joint, weight)| {
+            let matrix = joint_matrices.get(*joint as usize)
+                .unwrap_or_else(|| panic!("joint {} is outside the {} joint matrices bound for skinning", joint, joint_matrices.len()));
+            sum + *matrix * (weight / total)
+        })
+}
+
+//Normals don't take the translation, and need the inverse transpose to stay perpendicular under non uniform scale
+pub fn normal_matrix(matrix: Mat4) -> Mat3 {
+    Mat3::from_mat4(matrix).inverse().transpose()
+}
+
+//Skinned positions and normals, normals stay empty when the vertices have none
+pub(crate) fn skin_positions_and_normals(vertices: &VertexInput, joint_matrices: &[Mat4]) -> (Vec<Vec3>, Vec<Vec3>) {
+    assert_skin_covers_vertices(vertices);
+
+    let mut positions = Vec::with_capacity(vertices.len());
+    let mut normals = Vec::with_capacity(vertices.normals.len());
+
+    for i in 0..vertices.len() {
+        let matrix = skin_matrix(vertices.joints[i], vertices.weights[i], joint_matrices);
+        positions.push(matrix.transform_point3(vertices.positions[i]));
+        if vertices.has_normals() { normals.push((normal_matrix(matrix) * vertices.normals[i]).normalize_or_zero()); }
+    }
+
+    (positions, normals)
+}
+
+fn assert_skin_covers_vertices(vertices: &VertexInput) {
+    assert!(
+        vertices.joints.len() == vertices.len() && vertices.weights.len() == vertices.len(),
+        "skinned vertices need joints and weights for every vertex, got {} positions, {} joints and {} weights",
+        vertices.len(), vertices.joints.len(), vertices.weights.len()
+    );
+}
+
+//Skinned copy of the vertices with positions, normals and tangents moved by the joints.
+//Vertices without joints are returned as they are
+pub fn skin(vertices: &VertexInput, joint_matrices: &[Mat4]) -> VertexInput {
+
+    let mut skinned = vertices.clone();
+    if !vertices.has_skin() { return skinned; }
+
+    (skinned.positions, skinned.normals) = skin_positions_and_normals(vertices, joint_matrices);
+
+    if vertices.has_tangents() {
+        for (tangent, (joints, weights)) in skinned.tangents.iter_mut().zip(vertices.joints.iter().zip(&vertices.weights)) {
+            let matrix = skin_matrix(*joints, *weights, joint_matrices);
+            *tangent = matrix.transform_vector3(tangent.truncate()).normalize_or_zero().extend(tangent.w);
+        }
+    }
+
+    skinned
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
+
+    use super::{skin, skin_matrix, Skeleton};
+    use crate::renderer::data::VertexInput;
+    use crate::scene::Transform;
+
+    #[test]
+    fn weights_blend_joint_matrices() {
+        let matrices = [Mat4::IDENTITY, Mat4::from_translation(Vec3::X * 2.0)];
+
+        let half = skin_matrix([0, 1, 0, 0], Vec4::new(1.0, 1.0, 0.0, 0.0), &matrices);
+        assert!(half.transform_point3(Vec3::ZERO).abs_diff_eq(Vec3::X, 1e-6));
+        assert_eq!(skin_matrix([1, 0, 0, 0], Vec4::ZERO, &matrices), Mat4::IDENTITY);
+    }
+
+    #[test]
+    fn bending_a_joint_moves_its_vertices() {
+        //Two joints in a line up y, the vertex at the top follows the second
+        let mut skeleton = Skeleton::default();
+        let root = skeleton.add_joint("root", None, Transform::default());
+        let elbow = skeleton.add_joint("elbow", Some(root), Transform::from_translation(Vec3::Y));
+
+        let vertices = VertexInput {
+            positions: vec![Vec3::ZERO, Vec3::new(0.0, 2.0, 0.0)],
+            colours: vec![Vec3::ONE; 2],
+            uvs: vec![Vec2::ZERO; 2],
+            normals: vec![Vec3::X; 2],
+            joints: vec![[root as u16, 0, 0, 0], [elbow as u16, 0, 0, 0]],
+            weights: vec![Vec4::X; 2],
+            ..Default::default()
+        };
+
+        //The rest pose is the bind pose, so nothing moves
+        let rest = skeleton.joint_matrices(&skeleton.rest_pose());
+        assert!(rest.iter().all(|matrix| matrix.abs_diff_eq(Mat4::IDENTITY, 1e-6)));
+
+        let mut pose = skeleton.rest_pose();
+        pose.locals[elbow].rotation = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+        let skinned = skin(&vertices, &skeleton.joint_matrices(&pose));
+
+        assert_eq!(skinned.positions[0], Vec3::ZERO);
+        assert!(skinned.positions[1].abs_diff_eq(Vec3::new(-1.0, 1.0, 0.0), 1e-6), "{:?}", skinned.positions[1]);
+        assert!(skinned.normals[1].abs_diff_eq(Vec3::Y, 1e-6));
+        assert_eq!(skinned.normals[0], Vec3::X);
+    }
+}
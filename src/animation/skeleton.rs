@@ -0,0 +1,70 @@
+use glam::Mat4;
+
+use crate::scene::Transform;
+
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub name: String,
+    //Parents always come before their children
+    pub parent: Option<usize>,
+    //Local transform when nothing animates the joint
+    pub rest: Transform,
+    //From the joint's space into model space when the mesh was bound, and back
+    pub bind: Mat4,
+    pub inverse_bind: Mat4
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>
+}
+
+//Local transform of every joint of a skeleton
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pose {
+    pub locals: Vec<Transform>
+}
+
+impl Skeleton {
+
+    pub fn len(&self) -> usize { self.joints.len() }
+    pub fn is_empty(&self) -> bool { self.joints.is_empty() }
+
+    //Binds the joint where its rest transform puts it
+    pub fn add_joint(&mut self, name: &str, parent: Option<usize>, rest: Transform) -> usize {
+        debug_assert!(parent.is_none_or(|parent| parent < self.joints.len()), "Parents have to be added first");
+
+        let parent_bind = parent.map_or(Mat4::IDENTITY, |parent| self.joints[parent].bind);
+        let bind = parent_bind * rest.to_matrix();
+
+        self.joints.push(Joint { name: name.to_string(), parent, rest, bind, inverse_bind: bind.inverse() });
+        self.joints.len() - 1
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+
+    pub fn rest_pose(&self) -> Pose {
+        Pose { locals: self.joints.iter().map(|joint| joint.rest).collect() }
+    }
+
+    //Joint to model space for every joint of the pose
+    pub fn model_matrices(&self, pose: &Pose) -> Vec<Mat4> {
+        debug_assert_eq!(pose.locals.len(), self.joints.len());
+
+        let mut matrices: Vec<Mat4> = Vec::with_capacity(self.joints.len());
+        for (joint, local) in self.joints.iter().zip(&pose.locals) {
+            let parent = joint.parent.map_or(Mat4::IDENTITY, |parent| matrices[parent]);
+            matrices.push(parent * local.to_matrix());
+        }
+        matrices
+    }
+
+    //What the vertex shader takes, moves bind pose vertices to where the pose puts them
+    pub fn joint_matrices(&self, pose: &Pose) -> Vec<Mat4> {
+        self.model_matrices(pose).iter().zip(&self.joints)
+            .map(|(model, joint)| *model * joint.inverse_bind)
+            .collect()
+    }
+}
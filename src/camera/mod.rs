@@ -1,3 +1,4 @@
+use crate::math::bounding_volume::Sphere;
 use crate::math::plane::Plane;
 
 #[derive(Default, Clone)]
@@ -58,6 +59,14 @@ impl Camera {
         vec![near, far, right, left, top, bottom]
     }
 
+    //Fraction of the screen height the sphere covers, infinite with the camera inside it
+    pub fn projected_size(&self, sphere: &Sphere) -> f32 {
+        let distance = sphere.center.distance(self.position);
+        if distance <= sphere.radius { return f32::INFINITY; }
+
+        sphere.radius / (distance * (self.fov * 0.5).tan())
+    }
+
     pub fn generate_view_projection(&self) -> (glam::Mat4, glam::Mat4) {
         (
             glam::Mat4::from_rotation_translation(self.get_rotation(), self.position).inverse(),
@@ -81,7 +90,6 @@ pub fn first_person_controls(camera : &mut Camera, input: &minifb::Window, mouse
 
     let camera_front = camera.get_front();
     let camera_right = camera.get_right();
-    let camera_up = camera.get_up();
     
     //Movement
     let mut movement_delta = glam::Vec3::default();
@@ -99,7 +107,7 @@ pub fn first_person_controls(camera : &mut Camera, input: &minifb::Window, mouse
     );
 
     //Normalize and apply movement
-    if movement_delta.dot(movement_delta).abs() > std::f32::EPSILON {
+    if movement_delta.dot(movement_delta).abs() > f32::EPSILON {
         camera.position += movement_delta.normalize() * dt;
     }
 }
\ No newline at end of file
@@ -0,0 +1,7 @@
+pub mod math;
+pub mod texture;
+pub mod camera;
+pub mod renderer;
+pub mod scene;
+pub mod mesh;
+pub mod animation;
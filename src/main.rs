@@ -3,17 +3,12 @@ use glam::Vec2;
 
 use minifb::Window;
 
-mod math;
-use math::*;
-
-mod texture;
-use texture::*;
-
-mod camera;
-use camera::*;
-
-mod renderer;
-use renderer::*;
+use rusterizer_s::animation;
+use rusterizer_s::math::colour;
+use rusterizer_s::texture::*;
+use rusterizer_s::camera::*;
+use rusterizer_s::renderer::*;
+use rusterizer_s::scene::*;
 
 
 
@@ -21,30 +16,12 @@ const RESOLUTION_WIDTH: usize = 640;
 const RESOLUTION_HEIGHT: usize = 480; 
 const UPSCALE: usize = 1;
 
-const QUAD_INDICES: [usize; 6] = [
-    0, 1, 2,
-    2, 3, 0
-];
-
-const QUAD_VERTEX_POSITIONS: [Vec3; 4] = [
-    Vec3::new(-0.5,  0.5, 0.5),
-    Vec3::new(-0.5, -0.5, 0.5),
-    Vec3::new(0.5, -0.5, 0.5),
-    Vec3::new(0.5,  0.5, 0.5),  
-];
-
-const QUAD_VERTEX_UVS: [Vec2; 4] = [
-    Vec2::new(0.0, 1.0),
-    Vec2::new(0.0, 0.0),
-    Vec2::new(1.0, 0.0),
-    Vec2::new(1.0, 1.0)
-];
-
-
 fn create_window() -> minifb::Result<Window> {
-    let mut window_options = minifb::WindowOptions::default();
-    window_options.scale_mode = minifb::ScaleMode::Stretch;
-    window_options.resize = false;
+    let window_options = minifb::WindowOptions {
+        scale_mode: minifb::ScaleMode::Stretch,
+        resize: false,
+        ..Default::default()
+    };
 
     Window::new("Rasterizing with Rust", RESOLUTION_WIDTH * UPSCALE, RESOLUTION_HEIGHT * UPSCALE, window_options)
 }
@@ -53,34 +30,105 @@ fn main() {
     
     let mut window = create_window().unwrap();
     
-    let mut output_surface = Texture::new(RESOLUTION_WIDTH, RESOLUTION_HEIGHT);
+    //The window expects sRGB, shading happens in linear space
+    let mut output_surface = Texture::new(RESOLUTION_WIDTH, RESOLUTION_HEIGHT).with_colour_space(colour::ColourSpace::Srgb);
+    let mut hdr_surface = FloatTexture::new(RESOLUTION_WIDTH, RESOLUTION_HEIGHT);
     let mut depth_attachment = DepthTexture::new(RESOLUTION_WIDTH, RESOLUTION_HEIGHT);
+    let mut depth_pyramid = hiz::HiZBuffer::new(RESOLUTION_WIDTH, RESOLUTION_HEIGHT);
 
     let mut timer = std::time::Instant::now();
 
     //Camera
-    let mut camera = Camera::default();
-    camera.position = Vec3::new(0.0, 0.0, 1.0);
-    camera.aspect_ratio = (RESOLUTION_WIDTH as f32) / (RESOLUTION_HEIGHT as f32);
-    camera.fov = std::f32::consts::PI * 0.25;
-    camera.near = 0.1;
-    camera.far = 10.0;
-
-    //Shader abstractions
-    let mut vs = vertex::VertexShader::default();
-    let mut fs = fragment::FragmentShader::default();
-    let mut ls = debug::DebugLineShader::default();
+    let mut camera = Camera {
+        position: Vec3::new(0.0, 0.0, 1.0),
+        aspect_ratio: (RESOLUTION_WIDTH as f32) / (RESOLUTION_HEIGHT as f32),
+        fov: std::f32::consts::PI * 0.25,
+        near: 0.1,
+        far: 10.0,
+        ..Default::default()
+    };
 
     let texture = load_image_file(std::path::Path::new("assets/icon.jpeg")).unwrap();
-    fs.mesh_texture = texture;
 
-    //Setting up vertices
-    let mut vertices = data::VertexInput::default();
-    vertices.positions = QUAD_VERTEX_POSITIONS.to_vec();
-    vertices.colours = QUAD_VERTEX_UVS.iter().map(|vec2|{ Vec3::new(vec2.x, vec2.y, 1.0) }).collect();
-    vertices.uvs = QUAD_VERTEX_UVS.to_vec();
-
-    let indices = QUAD_INDICES.to_owned();
+    //Scene, a cube coloured by its uvs
+    let (mut vertices, indices) = rusterizer_s::mesh::primitives::cube(Vec3::ONE, 1);
+    vertices.colours = vertices.uvs.iter().map(|uv| Vec3::new(uv.x, uv.y, 1.0)).collect();
+
+    let mut scene = Scene::default();
+    let cube_mesh = scene.add_mesh(data::Mesh::new(vertices, indices));
+    let material = scene.add_material(Material { texture: std::rc::Rc::new(texture), ..Default::default() });
+
+    let cube = scene.add_node("cube", None, Transform::default());
+    scene.set_mesh(cube, cube_mesh, Some(material));
+
+    //Point ring around the cube
+    let ring_positions: Vec<Vec3> = (0..48).map(|i| {
+        let angle = (i as f32) / 48.0 * std::f32::consts::TAU;
+        Vec3::new(angle.cos(), 0.0, angle.sin())
+    }).collect();
+
+    let ring_vertices = data::VertexInput {
+        colours: ring_positions.iter().map(|p| *p * 0.5 + 0.5).collect(),
+        uvs: vec![Vec2::ZERO; ring_positions.len()],
+        positions: ring_positions,
+        ..Default::default()
+    };
+
+    let ring_indices: Vec<usize> = (0..ring_vertices.positions.len()).collect();
+    let ring = scene.add_mesh(data::Mesh::with_topology(ring_vertices, ring_indices, data::Topology::PointList));
+    let ring_node = scene.add_node("ring", Some(cube), Transform::default());
+    scene.set_mesh(ring_node, ring, None);
+
+    //Ribbon next to the cube, bending at the middle with a looping clip
+    let (mut ribbon_vertices, ribbon_indices) = rusterizer_s::mesh::primitives::plane(Vec2::new(0.15, 1.0), glam::UVec2::new(1, 10));
+    for (position, normal) in ribbon_vertices.positions.iter_mut().zip(&mut ribbon_vertices.normals) {
+        *position = Vec3::new(position.x, 0.5 - position.z, 0.0);
+        *normal = Vec3::Z;
+    }
+    ribbon_vertices.colours = ribbon_vertices.uvs.iter().map(|uv| Vec3::new(1.0, uv.y, 0.2)).collect();
+    ribbon_vertices.joints = vec![[0, 1, 0, 0]; ribbon_vertices.len()];
+    ribbon_vertices.weights = ribbon_vertices.positions.iter().map(|position| {
+        let bend = ((position.y - 0.3) / 0.4).clamp(0.0, 1.0);
+        glam::Vec4::new(1.0 - bend, bend, 0.0, 0.0)
+    }).collect();
+
+    //Culling only sees the bind pose, so the bounds cover the whole swing
+    let mut ribbon_mesh = data::Mesh::new(ribbon_vertices, ribbon_indices);
+    ribbon_mesh.bounds = rusterizer_s::math::bounding_volume::BoundingVolumes::from_points(&[Vec3::new(-1.0, -0.1, -0.1), Vec3::new(1.0, 1.1, 0.1)]);
+    let ribbon_mesh = std::rc::Rc::new(ribbon_mesh);
+    let ribbon_model = glam::Mat4::from_translation(Vec3::new(1.2, -0.5, -1.5));
+    let white = std::rc::Rc::new(Texture::solid(0xFFFFFFFF));
+
+    let mut skeleton = animation::Skeleton::default();
+    let root = skeleton.add_joint("root", None, Transform::default());
+    let elbow = skeleton.add_joint("elbow", Some(root), Transform::from_translation(Vec3::new(0.0, 0.5, 0.0)));
+
+    let sway = |angles: [f32; 3]| animation::Keyframes::Rotation(angles.iter().map(|angle| glam::Quat::from_rotation_z(*angle)).collect());
+    let clip = animation::AnimationClip {
+        name: "sway".to_string(),
+        channels: vec![
+            animation::Channel { joint: root, times: vec![0.0, 1.0, 2.0], keyframes: sway([-0.3, 0.3, -0.3]), interpolation: animation::Interpolation::Linear },
+            animation::Channel { joint: elbow, times: vec![0.0, 1.0, 2.0], keyframes: sway([-0.9, 0.9, -0.9]), interpolation: animation::Interpolation::Linear }
+        ],
+        looping: true
+    };
+    let mut pose = skeleton.rest_pose();
+    let mut animation_time = 0.0;
+
+    let mut commands = command::CommandBuffer::default();
+    let mut pipeline = command::PipelineState {
+        point: state::PointState { size: 4.0, shape: state::PointShape::Round, sprite: false },
+        ..Default::default()
+    };
+
+    //Debug overlay
+    let mut ls = debug::DebugLineShader { mode: debug::LineMode::AntiAliased, ..Default::default() };
+    let mut debug_draw = debug_draw::DebugDraw::default();
+    let mut hud = hud::Hud::default();
+    let mut tone_mapper = tonemap::ToneMapper::new(
+        tonemap::ToneMapOperator::AcesFitted,
+        tonemap::Exposure::Automatic(tonemap::AutoExposure::default())
+    );
     let mut prev_mouse = Vec2::default();
 
     while window.is_open() {
@@ -100,33 +148,68 @@ fn main() {
 
         //camera controls
         first_person_controls(&mut camera, &window, mouse_delta, dt);
-        let (view, projection) = camera.generate_view_projection();
-        vs.view = view;
-        vs.projection = projection;
 
-        //clear
-        output_surface.clear(colour::f32_to_hex(1.0, 0.0, 0.0, 0.0));
-        depth_attachment.clear(1.0);
+        //F cycles the fill mode
+        if window.is_key_pressed(minifb::Key::F, minifb::KeyRepeat::No) {
+            pipeline.raster.fill_mode = match pipeline.raster.fill_mode {
+                state::FillMode::Solid => state::FillMode::Wireframe,
+                state::FillMode::Wireframe => state::FillMode::SolidWireframe,
+                state::FillMode::SolidWireframe => state::FillMode::Solid,
+            };
+        }
+
+        //record
+        commands.reset();
+        commands.clear(Some(glam::Vec4::new(0.0, 0.0, 0.0, 1.0)), Some(1.0));
+        commands.bind_pipeline(std::rc::Rc::new(pipeline.clone()));
+        let culling_stats = record_scene(&mut scene, &camera, &mut commands);
+
+        animation_time += dt;
+        clip.sample(animation_time, &mut pose);
+        commands.bind_texture(white.clone(), Sampler::default());
+        commands.set_joint_matrices(skeleton.joint_matrices(&pose).into());
+        commands.draw_indexed(ribbon_mesh.clone(), ribbon_model);
+        commands.sort_draws();
 
         //draw
+        let mut targets = RenderTargets::new(&mut hdr_surface, &mut depth_attachment).with_hiz(&mut depth_pyramid);
+        let draw_stats = commands.submit(&mut targets);
+
+        //T cycles the tone mapping operator
+        if window.is_key_pressed(minifb::Key::T, minifb::KeyRepeat::No) {
+            tone_mapper.operator = match tone_mapper.operator {
+                tonemap::ToneMapOperator::AcesFitted => tonemap::ToneMapOperator::Reinhard,
+                tonemap::ToneMapOperator::Reinhard => tonemap::ToneMapOperator::ExtendedReinhard { white_point: 4.0 },
+                tonemap::ToneMapOperator::ExtendedReinhard { .. } => tonemap::ToneMapOperator::Uncharted2 { white_point: 11.2 },
+                _ => tonemap::ToneMapOperator::AcesFitted,
+            };
+        }
+
+        tone_mapper.resolve(&hdr_surface, &mut output_surface, dt);
+
+        //debug
+        ls.camera = camera.clone();
+        debug_draw.grid(Vec3::new(0.0, -0.5, 0.0), 0.5, 6, glam::Vec4::new(0.5, 0.5, 0.5, 1.0), 1);
+        debug_draw.axes(&glam::Mat4::IDENTITY, 0.25, 1);
+        debug_draw.flush(&ls, &mut output_surface, Some(&depth_attachment));
+
+        //H toggles the stats overlay
+        if window.is_key_pressed(minifb::Key::H, minifb::KeyRepeat::No) {
+            hud.enabled = !hud.enabled;
+        }
+
+        hud.update(dt);
+        hud.draw(&mut output_surface, &draw_stats, &culling_stats);
 
-        let model_matrices = [
-            glam::Mat4::IDENTITY,
-            glam::Mat4::from_rotation_y(std::f32::consts::PI * 0.5),
-            glam::Mat4::from_rotation_y(std::f32::consts::PI * 1.0),
-            glam::Mat4::from_rotation_y(std::f32::consts::PI * 1.5),
-            glam::Mat4::from_rotation_x(std::f32::consts::PI * 0.5),
-            glam::Mat4::from_rotation_x(std::f32::consts::PI * -0.5),
-        ];
-
-        for model in model_matrices { 
-            vs.model = model;  
-            let (t, i) = vs.dispatch(&vertices, &indices);
-            fs.dispatch(&mut output_surface, &mut depth_attachment, &t, &i);
+        //P saves the frame and its depth to the working directory
+        if window.is_key_pressed(minifb::Key::P, minifb::KeyRepeat::No) {
+            let saved = output_surface.save(std::path::Path::new("screenshot.png")).and_then(|_| {
+                depth_attachment.save_depth(std::path::Path::new("screenshot_depth.png"), export::DepthExport::from_camera(&camera))
+            });
+            if let Err(error) = saved { eprintln!("Screenshot failed: {}", error); }
         }
 
         window.update_with_buffer(output_surface.as_slice(), RESOLUTION_WIDTH, RESOLUTION_HEIGHT).unwrap();
-        //dbg!(dt);
     }
 
 }
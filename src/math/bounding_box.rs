@@ -26,7 +26,7 @@ impl Line {
 
         //let div = (self.start.x - self.end.x) * (line.start.y - line.end.y) - (self.start.y - self.end.y) * (line.start.x - line.end.x);
         let div = (x1 - x2) * (y3 - y4) - (y1 - y2) * (x3 - x4); 
-        if div.abs() < std::f32::EPSILON { return None; }
+        if div.abs() < f32::EPSILON { return None; }
 
         let num2 = (x1 - x2) * (y1 - y3) - (y1 - y2) * (x1 - x3);
         
@@ -0,0 +1,112 @@
+use glam::Mat4;
+use glam::Vec3;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3
+}
+
+impl Aabb {
+
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_points(points: &[Vec3]) -> Self {
+
+        if points.is_empty() { return Self::default(); }
+
+        let (min, max) = points.iter().fold((Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)), |(min, max), p| {
+            (min.min(*p), max.max(*p))
+        });
+
+        Self { min, max }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        [
+            Vec3::new(self.min.x, self.min.y, self.min.z),
+            Vec3::new(self.max.x, self.min.y, self.min.z),
+            Vec3::new(self.max.x, self.max.y, self.min.z),
+            Vec3::new(self.min.x, self.max.y, self.min.z),
+            Vec3::new(self.min.x, self.min.y, self.max.z),
+            Vec3::new(self.max.x, self.min.y, self.max.z),
+            Vec3::new(self.max.x, self.max.y, self.max.z),
+            Vec3::new(self.min.x, self.max.y, self.max.z),
+        ]
+    }
+
+    //Transformed box still axis aligned, so it encloses the rotated one
+    pub fn transform(&self, matrix: &Mat4) -> Self {
+
+        let center = matrix.transform_point3(self.center());
+        let extents = self.half_extents();
+
+        let abs_matrix = glam::Mat3::from_cols(
+            matrix.x_axis.truncate().abs(),
+            matrix.y_axis.truncate().abs(),
+            matrix.z_axis.truncate().abs()
+        );
+
+        let new_extents = abs_matrix.mul_vec3(extents);
+        Self { min: center - new_extents, max: center + new_extents }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32
+}
+
+impl Sphere {
+
+    pub fn new(center: Vec3, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    //Centered on the AABB, not minimal but cheap and stable
+    pub fn from_points(points: &[Vec3]) -> Self {
+
+        let center = Aabb::from_points(points).center();
+        let radius_squared = points.iter().fold(0.0f32, |radius, p| { radius.max(p.distance_squared(center)) });
+
+        Self { center, radius: radius_squared.sqrt() }
+    }
+
+    pub fn transform(&self, matrix: &Mat4) -> Self {
+
+        let center = matrix.transform_point3(self.center);
+
+        let max_scale_squared = matrix.x_axis.truncate().length_squared()
+            .max(matrix.y_axis.truncate().length_squared())
+            .max(matrix.z_axis.truncate().length_squared());
+
+        Self { center, radius: self.radius * max_scale_squared.sqrt() }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BoundingVolumes {
+    pub aabb: Aabb,
+    pub sphere: Sphere
+}
+
+impl BoundingVolumes {
+    pub fn from_points(points: &[Vec3]) -> Self {
+        Self { aabb: Aabb::from_points(points), sphere: Sphere::from_points(points) }
+    }
+
+    pub fn transform(&self, matrix: &Mat4) -> Self {
+        Self { aabb: self.aabb.transform(matrix), sphere: self.sphere.transform(matrix) }
+    }
+}
@@ -28,9 +28,100 @@ pub fn vec4_to_hex(v: glam::Vec4) -> u32 {
 //ARGB -> RGBA
 pub fn hex_to_f32(hex: u32) -> glam::Vec4 {
     glam::Vec4::new(
-        u8_to_f32((hex) as u8),
-        u8_to_f32((hex >> 8) as u8),
         u8_to_f32((hex >> 16) as u8),
+        u8_to_f32((hex >> 8) as u8),
+        u8_to_f32((hex) as u8),
         u8_to_f32((hex >> 24) as u8)
     )
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColourSpace {
+    #[default]
+    Linear,
+    Srgb
+}
+
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 { v / 12.92 }
+    else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+pub fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 { v * 12.92 }
+    else { 1.055 * v.powf(1.0 / 2.4) - 0.055 }
+}
+
+const ENCODE_LUT_SIZE: usize = 4096;
+
+fn decode_lut() -> &'static [f32; 256] {
+    static LUT: std::sync::OnceLock<[f32; 256]> = std::sync::OnceLock::new();
+    LUT.get_or_init(|| std::array::from_fn(|i| srgb_to_linear(u8_to_f32(i as u8))))
+}
+
+fn encode_lut() -> &'static [u8; ENCODE_LUT_SIZE] {
+    static LUT: std::sync::OnceLock<[u8; ENCODE_LUT_SIZE]> = std::sync::OnceLock::new();
+    LUT.get_or_init(|| std::array::from_fn(|i| {
+        let encoded = linear_to_srgb(i as f32 / (ENCODE_LUT_SIZE - 1) as f32);
+        (encoded * 255.0 + 0.5) as u8
+    }))
+}
+
+//Table based versions of the conversions, for 8 bit storage
+pub fn srgb_u8_to_linear(v: u8) -> f32 {
+    decode_lut()[v as usize]
+}
+
+pub fn linear_to_srgb_u8(v: f32) -> u8 {
+    let index = (v.clamp(0.0, 1.0) * (ENCODE_LUT_SIZE - 1) as f32 + 0.5) as usize;
+    encode_lut()[index]
+}
+
+//Like hex_to_f32 but decodes the colour channels, alpha is always linear
+pub fn srgb_hex_to_linear(hex: u32) -> glam::Vec4 {
+    glam::Vec4::new(
+        srgb_u8_to_linear((hex >> 16) as u8),
+        srgb_u8_to_linear((hex >> 8) as u8),
+        srgb_u8_to_linear(hex as u8),
+        u8_to_f32((hex >> 24) as u8)
+    )
+}
+
+pub fn linear_to_srgb_hex(v: glam::Vec4) -> u32 {
+    u8_to_hex(f32_to_u8(v.w), linear_to_srgb_u8(v.x), linear_to_srgb_u8(v.y), linear_to_srgb_u8(v.z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hex_to_f32, linear_to_srgb, linear_to_srgb_u8, srgb_to_linear, srgb_u8_to_linear, u8_to_hex, vec4_to_hex};
+
+    #[test]
+    fn hex_decodes_argb() {
+        //Packed the way load_image_file packs texels
+        let hex = u8_to_hex(0x80, 0xFF, 0x40, 0x00);
+        let colour = hex_to_f32(hex);
+
+        assert_eq!(colour.x, 1.0);
+        assert!((colour.y - 0x40 as f32 / 255.0).abs() < 1e-6);
+        assert_eq!(colour.z, 0.0);
+        assert_eq!(vec4_to_hex(colour), hex);
+    }
+
+    #[test]
+    fn srgb_lut_round_trip() {
+        for i in 0..=255u8 {
+            let linear = srgb_u8_to_linear(i);
+            assert!((linear - srgb_to_linear(i as f32 / 255.0)).abs() < 1e-6);
+            assert_eq!(linear_to_srgb_u8(linear), i);
+        }
+    }
+
+    #[test]
+    fn srgb_encode_matches_exact() {
+        for i in 0..=1000 {
+            let linear = i as f32 / 1000.0;
+            let exact = (linear_to_srgb(linear) * 255.0).round() as i32;
+            assert!((linear_to_srgb_u8(linear) as i32 - exact).abs() <= 1);
+        }
+    }
+}
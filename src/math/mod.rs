@@ -1,10 +1,9 @@
-use glam::Mat4;
-use glam::Quat;
 use glam::Vec2;
 use glam::Vec3;
 use glam::Vec4;
 
 pub mod bounding_box;
+pub mod bounding_volume;
 pub mod colour;
 pub mod plane;
 
@@ -22,38 +21,82 @@ pub fn homogenous_clip(a: Vec4, b: Vec4, plane: Vec4) -> Option<f32> {
     let line_vector = b - a;
 
     let div = plane.dot(line_vector);
-    if div.abs() < std::f32::EPSILON { return None; }
+    if div.abs() < f32::EPSILON { return None; }
 
     let t = -plane.dot(a) / div;
     if t > 0.0 && t < 1.0 { Some(t) }
     else { None }
 }
 
+pub const CLIP_PLANES: [Vec4; 6] = [
+    Vec4::new(1.0, 0.0, 0.0, 1.0), //Left
+    Vec4::new(-1.0, 0.0, 0.0, 1.0), //Right
+    Vec4::new(0.0, 1.0, 0.0, 1.0), //Bottom
+    Vec4::new(0.0, -1.0, 0.0, 1.0), //Top
+    Vec4::new(0.0, 0.0, 1.0, 0.0), //Near
+    Vec4::new(0.0, 0.0, -1.0, 1.0), //Far
+];
+
+pub const NEAR_PLANE: Vec4 = CLIP_PLANES[4];
+pub const FAR_PLANE: Vec4 = CLIP_PLANES[5];
+
+//How far past the screen, in multiples of w, triangles can go before the sides are clipped.
+//Up to there the rasterizer's bounding box intersection takes care of it
+pub const GUARD_BAND: f32 = 8.0;
+
+pub const GUARD_BAND_PLANES: [Vec4; 6] = [
+    Vec4::new(1.0, 0.0, 0.0, GUARD_BAND),
+    Vec4::new(-1.0, 0.0, 0.0, GUARD_BAND),
+    Vec4::new(0.0, 1.0, 0.0, GUARD_BAND),
+    Vec4::new(0.0, -1.0, 0.0, GUARD_BAND),
+    NEAR_PLANE,
+    FAR_PLANE,
+];
+
+//Returns the clipped segment and the parameters of its ends along the original one
+pub fn clip_homogenous_line(start: Vec4, end: Vec4, planes: &[Vec4]) -> Option<(Vec4, Vec4, f32, f32)> {
+
+    let (mut t_start, mut t_end) = (0.0f32, 1.0f32);
+
+    for plane in planes {
+        let start_distance = plane.dot(start);
+        let end_distance = plane.dot(end);
+
+        if start_distance < 0.0 && end_distance < 0.0 { return None; }
+
+        if start_distance < 0.0 {
+            t_start = t_start.max(start_distance / (start_distance - end_distance));
+        } else if end_distance < 0.0 {
+            t_end = t_end.min(start_distance / (start_distance - end_distance));
+        }
+    }
+
+    if t_start > t_end { return None; }
+    Some((lerp(start, end, t_start), lerp(start, end, t_end), t_start, t_end))
+}
+
 pub fn clip_homogenous_triangle(vertices: &[Vec4; 3]) -> Vec<(Vec4, Vec3)> {
+    clip_homogenous_polygon(vertices, &CLIP_PLANES)
+}
 
-    let mut output_list: Vec<(Vec4, Vec3)> = vertices.iter()
-        .enumerate()
-        .map(|(i, v)| {
-            if i == 0 {
-                (v.clone(), Vec3::X)
-            } else if i == 1 {
-                (v.clone(), Vec3::Y)
-            } else {
-                (v.clone(), Vec3::Z)
-            }
-        })
-        .collect();
-
-    let clip_planes = [
-        Vec4::new(1.0, 0.0, 0.0, 1.0), //Left
-        Vec4::new(-1.0, 0.0, 0.0, 1.0), //Right
-        Vec4::new(0.0, 1.0, 0.0, 1.0), //Bottom
-        Vec4::new(0.0, -1.0, 0.0, 1.0), //Top
-        Vec4::new(0.0, 0.0, 1.0, 0.0), //Near
-        Vec4::new(0.0, 0.0, -1.0, 1.0), //Far
+//Only near, far and the guard band, the screen edges are left to the rasterizer
+pub fn clip_guard_band_triangle(vertices: &[Vec4; 3]) -> Vec<(Vec4, Vec3)> {
+    clip_homogenous_polygon(vertices, &GUARD_BAND_PLANES)
+}
+
+//Sutherland-Hodgman, returns the polygon with the barycentrics of each vertex in the triangle
+pub fn clip_homogenous_polygon(vertices: &[Vec4; 3], planes: &[Vec4]) -> Vec<(Vec4, Vec3)> {
+
+    let mut output_list: Vec<(Vec4, Vec3)> = vec![
+        (vertices[0], Vec3::X),
+        (vertices[1], Vec3::Y),
+        (vertices[2], Vec3::Z)
     ];
 
-    for plane in clip_planes {
+    for plane in planes {
+
+        //Nothing to cut
+        if output_list.iter().all(|(v, _)| plane.dot(*v) >= 0.0) { continue; }
 
         let input_list = output_list.clone();
         output_list.clear();
@@ -69,7 +112,7 @@ pub fn clip_homogenous_triangle(vertices: &[Vec4; 3]) -> Vec<(Vec4, Vec3)> {
                 output_list.push(current_point);
             }
 
-            if let Some(t) = homogenous_clip(current_point.0, next_point.0, plane) {
+            if let Some(t) = homogenous_clip(current_point.0, next_point.0, *plane) {
 
                 let interpolated = lerp(current_point.0, next_point.0, t);
                 let barycentric_coords =  lerp(current_point.1, next_point.1, t);
@@ -129,15 +172,9 @@ pub fn generate_triangle_bounding_box(v1: Vec2, v2: Vec2, v3: Vec2) -> bounding_
     bounding_box::BoundingBox { start: v_min.as_uvec2(), end: v_max.as_uvec2() }
 }
 
-pub fn triangle_in_bounds(v1: Vec4, v2: Vec4, v3: Vec4) -> bool {
-
-    let in_range = |v: Vec4| -> bool {
-        v.x > -v.w && v.x < v.w &&
-        v.y > -v.w && v.y < v.w &&
-        v.z >  0.0 && v.z < v.w
-    };
-
-    in_range(v1) || in_range(v2) || in_range(v3)
+//True when no plane of clip_guard_band_triangle would cut the triangle
+pub fn triangle_in_guard_band(v1: Vec4, v2: Vec4, v3: Vec4) -> bool {
+    GUARD_BAND_PLANES.iter().all(|plane| plane.dot(v1) >= 0.0 && plane.dot(v2) >= 0.0 && plane.dot(v3) >= 0.0)
 }
 
 pub fn lerp<T>(start: T, end: T, alpha: f32) -> T
@@ -1,4 +1,6 @@
-use glam::{Vec3, Vec4};
+use glam::Vec3;
+use super::bounding_volume::Aabb;
+use super::bounding_volume::Sphere;
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct Plane {
@@ -16,9 +18,11 @@ impl Plane {
         Plane::new(normal, point.dot(normal))
     }
 
+    pub fn normal(&self) -> Vec3 { self.normal }
+    pub fn d(&self) -> f32 { self.d }
+
     pub fn signed_distance(&self, point: Vec3) -> f32 {
-        let res = self.normal.dot(point) - self.d;
-        res
+        self.normal.dot(point) - self.d
     }
 
     pub fn intersect(&self, start: Vec3, end: Vec3) -> Option<f32> {
@@ -30,9 +34,39 @@ impl Plane {
 
         let t = (self.d - self.normal.dot(start)) / normal_dir_dot;
 
-        if t >= 0.0 && t <= 1.0 { Some(t) }
+        if (0.0..=1.0).contains(&t) { Some(t) }
         else { None }
     }
+
+    //Entirely on the negative side of the plane
+    pub fn is_sphere_outside(&self, sphere: &Sphere) -> bool {
+        self.signed_distance(sphere.center) < -sphere.radius
+    }
+
+    pub fn is_aabb_outside(&self, aabb: &Aabb) -> bool {
+        //Projected radius of the box onto the plane normal
+        let radius = aabb.half_extents().dot(self.normal.abs());
+        self.signed_distance(aabb.center()) < -radius
+    }
+}
+
+//Point shared by three planes, None if any two are parallel
+pub fn intersect_planes(a: &Plane, b: &Plane, c: &Plane) -> Option<Vec3> {
+
+    let bc = b.normal.cross(c.normal);
+    let denominator = a.normal.dot(bc);
+    if denominator.abs() < f32::EPSILON { return None; }
+
+    let point = bc * a.d + c.normal.cross(a.normal) * b.d + a.normal.cross(b.normal) * c.d;
+    Some(point / denominator)
+}
+
+pub fn sphere_in_frustum(sphere: &Sphere, frustum: &[Plane]) -> bool {
+    !frustum.iter().any(|plane| plane.is_sphere_outside(sphere))
+}
+
+pub fn aabb_in_frustum(aabb: &Aabb, frustum: &[Plane]) -> bool {
+    !frustum.iter().any(|plane| plane.is_aabb_outside(aabb))
 }
 
 pub fn clip_polygon(polygon: &[Vec3], frustum: &[Plane]) -> Vec<Vec3> {
@@ -61,4 +95,47 @@ pub fn clip_polygon(polygon: &[Vec3], frustum: &[Plane]) -> Vec<Vec3> {
         }
     }
     output_list
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::{Plane, aabb_in_frustum, intersect_planes, sphere_in_frustum};
+    use crate::math::bounding_volume::{Aabb, Sphere};
+
+    //Unit box around the origin
+    fn box_frustum() -> Vec<Plane> {
+        vec![
+            Plane::from_normal_point(Vec3::X, Vec3::NEG_X),
+            Plane::from_normal_point(Vec3::NEG_X, Vec3::X),
+            Plane::from_normal_point(Vec3::Y, Vec3::NEG_Y),
+            Plane::from_normal_point(Vec3::NEG_Y, Vec3::Y),
+            Plane::from_normal_point(Vec3::Z, Vec3::NEG_Z),
+            Plane::from_normal_point(Vec3::NEG_Z, Vec3::Z),
+        ]
+    }
+
+    #[test]
+    fn sphere_culling() {
+        let frustum = box_frustum();
+        assert!(sphere_in_frustum(&Sphere::new(Vec3::ZERO, 0.5), &frustum));
+        assert!(sphere_in_frustum(&Sphere::new(Vec3::new(1.4, 0.0, 0.0), 0.5), &frustum));
+        assert!(!sphere_in_frustum(&Sphere::new(Vec3::new(1.6, 0.0, 0.0), 0.5), &frustum));
+    }
+
+    #[test]
+    fn three_plane_intersection() {
+        let frustum = box_frustum();
+        let corner = intersect_planes(&frustum[1], &frustum[3], &frustum[5]).unwrap();
+        assert!(corner.abs_diff_eq(Vec3::ONE, 1e-6));
+        assert!(intersect_planes(&frustum[0], &frustum[1], &frustum[2]).is_none());
+    }
+
+    #[test]
+    fn aabb_culling() {
+        let frustum = box_frustum();
+        assert!(aabb_in_frustum(&Aabb::new(Vec3::splat(0.5), Vec3::splat(2.0)), &frustum));
+        assert!(!aabb_in_frustum(&Aabb::new(Vec3::new(1.1, -1.0, -1.0), Vec3::splat(2.0)), &frustum));
+    }
+}
@@ -0,0 +1,66 @@
+//Processing for indexed triangle lists, mostly for meshes that come from outside the renderer
+
+pub mod normals;
+pub mod primitives;
+pub mod simplify;
+pub mod weld;
+
+pub use normals::{flat_normals, generate_tangents, smooth_normals, NormalWeighting, SmoothNormals};
+pub use simplify::{lod_chain, simplify, SimplifyOptions, Simplified};
+pub use weld::{weld, WeldTolerance};
+
+use glam::Vec3;
+
+use crate::math::bounding_volume::BoundingVolumes;
+use crate::renderer::data::VertexInput;
+
+//Bounds of the vertices the indices use, unlike VertexInput::bounding_volumes which takes all of them
+pub fn bounds(vertices: &VertexInput, indices: &[usize]) -> BoundingVolumes {
+    let points: Vec<Vec3> = indices.iter().map(|index| vertices.positions[*index]).collect();
+    BoundingVolumes::from_points(&points)
+}
+
+//Vertices at the same position share a key even when other attributes split them
+pub(crate) fn position_key(position: Vec3) -> [u32; 3] {
+    //Adding zero turns -0 into 0
+    (position + Vec3::ZERO).to_array().map(f32::to_bits)
+}
+
+//Drops the vertices no index uses, keeping the rest in order. Returns how many were removed
+pub(crate) fn remove_unused(vertices: &mut VertexInput, indices: &mut [usize]) -> usize {
+
+    let vertex_count = vertices.len();
+    let mut used = vec![false; vertex_count];
+    for index in indices.iter() { used[*index] = true; }
+
+    let kept: Vec<usize> = (0..vertex_count).filter(|vertex| used[*vertex]).collect();
+    let mut compact = vec![0; vertex_count];
+    for (new, old) in kept.iter().enumerate() { compact[*old] = new; }
+
+    for index in indices.iter_mut() { *index = compact[*index]; }
+    *vertices = vertices.select(&kept);
+
+    vertex_count - kept.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+
+    use super::bounds;
+    use crate::renderer::data::VertexInput;
+
+    #[test]
+    fn bounds_skip_unused_vertices() {
+        let vertices = VertexInput {
+            positions: vec![Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::splat(100.0)],
+            colours: vec![Vec3::ONE; 4],
+            uvs: vec![Vec2::ZERO; 4],
+            ..Default::default()
+        };
+
+        let volumes = bounds(&vertices, &[0, 1, 2]);
+        assert_eq!(volumes.aabb.max, Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(vertices.bounding_volumes().aabb.max, Vec3::splat(100.0));
+    }
+}
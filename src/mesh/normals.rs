@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+
+use glam::Vec3;
+use glam::Vec4;
+
+use crate::renderer::data::VertexInput;
+use super::position_key;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NormalWeighting {
+    //By the angle of each face at the vertex, so splitting a face doesn't change the result
+    #[default]
+    Angle,
+    Area
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmoothNormals {
+    pub weighting: NormalWeighting,
+    //Radians, faces meeting at a sharper angle than this keep a hard edge between them
+    pub crease_angle: f32
+}
+
+impl Default for SmoothNormals {
+    fn default() -> Self {
+        Self { weighting: NormalWeighting::Angle, crease_angle: std::f32::consts::PI }
+    }
+}
+
+//Unit normal, area and corner angles of a triangle
+fn face(vertices: &VertexInput, triangle: &[usize]) -> (Vec3, f32, [f32; 3]) {
+    let [a, b, c] = [vertices.positions[triangle[0]], vertices.positions[triangle[1]], vertices.positions[triangle[2]]];
+    let cross = (b - a).cross(c - a);
+
+    let angle = |corner: Vec3, next: Vec3, previous: Vec3| (next - corner).angle_between(previous - corner);
+    let angles = [angle(a, b, c), angle(b, c, a), angle(c, a, b)];
+
+    (cross.normalize_or_zero(), cross.length() * 0.5, angles.map(|a| if a.is_finite() { a } else { 0.0 }))
+}
+
+//Normals averaged over the faces around each position, vertices along a UV seam still smooth together.
+//Vertices on a crease get split so each side keeps its own normal, indices are updated to match
+pub fn smooth_normals(vertices: &mut VertexInput, indices: &mut [usize], options: SmoothNormals) {
+
+    let faces: Vec<_> = indices.chunks_exact(3).map(|triangle| face(vertices, triangle)).collect();
+
+    //Every corner touching a position, as (face, corner)
+    let mut corners_at: HashMap<[u32; 3], Vec<(usize, usize)>> = HashMap::new();
+    for (corner, index) in indices.iter().enumerate() {
+        corners_at.entry(position_key(vertices.positions[*index])).or_default().push((corner / 3, corner % 3));
+    }
+
+    let min_cos = options.crease_angle.cos() - 1e-6;
+
+    let corner_normals: Vec<Vec3> = indices.iter().enumerate().map(|(corner, index)| {
+        let own = faces[corner / 3].0;
+
+        let sum: Vec3 = corners_at[&position_key(vertices.positions[*index])].iter()
+            .filter(|(f, _)| own.dot(faces[*f].0) >= min_cos)
+            .map(|(f, k)| {
+                let (normal, area, angles) = faces[*f];
+                match options.weighting {
+                    NormalWeighting::Angle => normal * angles[*k],
+                    NormalWeighting::Area => normal * area
+                }
+            })
+            .sum();
+
+        sum.try_normalize().unwrap_or(own)
+    }).collect();
+
+    //A vertex keeps its first normal, corners that need another one get a copy of the vertex
+    let vertex_count = vertices.len();
+    let mut normals: Vec<Option<Vec3>> = vec![None; vertex_count];
+    let mut copies: HashMap<usize, Vec<(usize, Vec3)>> = HashMap::new();
+    let mut sources: Vec<usize> = (0..vertex_count).collect();
+    let mut extra_normals = Vec::new();
+
+    let same = |a: Vec3, b: Vec3| a.dot(b) > 1.0 - 1e-5;
+
+    for (index, normal) in indices.iter_mut().zip(&corner_normals) {
+        match normals[*index] {
+            None => normals[*index] = Some(*normal),
+            Some(existing) if same(existing, *normal) => {}
+            Some(_) => {
+                let vertex_copies = copies.entry(*index).or_default();
+                *index = match vertex_copies.iter().find(|(_, n)| same(*n, *normal)) {
+                    Some((copy, _)) => *copy,
+                    None => {
+                        let copy = sources.len();
+                        vertex_copies.push((copy, *normal));
+                        sources.push(*index);
+                        extra_normals.push(*normal);
+                        copy
+                    }
+                };
+            }
+        }
+    }
+
+    if sources.len() > vertex_count { *vertices = vertices.select(&sources); }
+
+    //Vertices no face uses keep whatever they had or point up
+    vertices.normals = normals.iter().enumerate()
+        .map(|(i, normal)| normal.unwrap_or_else(|| if i < vertices.normals.len() { vertices.normals[i] } else { Vec3::Y }))
+        .chain(extra_normals)
+        .collect();
+}
+
+//Every triangle gets its own three vertices with the face normal
+pub fn flat_normals(vertices: &mut VertexInput, indices: &mut [usize]) {
+
+    let face_normals: Vec<Vec3> = indices.chunks_exact(3).map(|triangle| face(vertices, triangle).0).collect();
+
+    *vertices = vertices.select(indices);
+    vertices.normals = face_normals.iter().flat_map(|normal| [*normal; 3]).collect();
+
+    for (i, index) in indices.iter_mut().enumerate() {
+        *index = i;
+    }
+}
+
+//Tangents along increasing u, from the uvs of the faces around each vertex. Needs normals
+pub fn generate_tangents(vertices: &mut VertexInput, indices: &[usize]) {
+
+    assert!(vertices.has_normals(), "tangents need normals");
+
+    let mut tangents = vec![Vec3::ZERO; vertices.len()];
+    let mut bitangents = vec![Vec3::ZERO; vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]];
+        let (edge_1, edge_2) = (vertices.positions[b] - vertices.positions[a], vertices.positions[c] - vertices.positions[a]);
+        let (duv_1, duv_2) = (vertices.uvs[b] - vertices.uvs[a], vertices.uvs[c] - vertices.uvs[a]);
+
+        let determinant = duv_1.perp_dot(duv_2);
+        if determinant.abs() < f32::EPSILON { continue; }
+
+        //Left unnormalized so bigger faces count for more
+        let tangent = (edge_1 * duv_2.y - edge_2 * duv_1.y) / determinant;
+        let bitangent = (edge_2 * duv_1.x - edge_1 * duv_2.x) / determinant;
+
+        for index in [a, b, c] {
+            tangents[index] += tangent;
+            bitangents[index] += bitangent;
+        }
+    }
+
+    vertices.tangents = tangents.iter().zip(&bitangents).zip(&vertices.normals).map(|((tangent, bitangent), normal)| {
+        //Gram-Schmidt against the normal
+        let tangent = (*tangent - *normal * normal.dot(*tangent)).try_normalize().unwrap_or_else(|| normal.any_orthonormal_vector());
+        let handedness = if normal.cross(tangent).dot(*bitangent) < 0.0 { -1.0 } else { 1.0 };
+        Vec4::from((tangent, handedness))
+    }).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+
+    use super::{flat_normals, generate_tangents, smooth_normals, NormalWeighting, SmoothNormals};
+    use crate::renderer::data::VertexInput;
+
+    //Unit cube sharing its 8 corners, counter clockwise from outside
+    fn cube() -> (VertexInput, Vec<usize>) {
+        let positions: Vec<Vec3> = (0..8).map(|i| Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32) - 0.5).collect();
+        let indices = vec![
+            0, 2, 3, 3, 1, 0, 4, 5, 7, 7, 6, 4,
+            0, 1, 5, 5, 4, 0, 2, 6, 7, 7, 3, 2,
+            0, 4, 6, 6, 2, 0, 1, 3, 7, 7, 5, 1
+        ];
+        let vertices = VertexInput { colours: vec![Vec3::ONE; 8], uvs: vec![Vec2::ZERO; 8], positions, ..Default::default() };
+        (vertices, indices)
+    }
+
+    #[test]
+    fn smooth_cube_normals_point_out_of_the_corners() {
+        let (mut vertices, mut indices) = cube();
+        smooth_normals(&mut vertices, &mut indices, SmoothNormals::default());
+
+        assert_eq!(vertices.len(), 8);
+        for (position, normal) in vertices.positions.iter().zip(&vertices.normals) {
+            assert!(normal.abs_diff_eq(position.normalize(), 1e-5), "{:?} at {:?}", normal, position);
+        }
+    }
+
+    #[test]
+    fn creases_split_vertices() {
+        let (mut vertices, mut indices) = cube();
+        smooth_normals(&mut vertices, &mut indices, SmoothNormals { crease_angle: 0.5, ..Default::default() });
+
+        //Three faces meet at every corner
+        assert_eq!(vertices.len(), 24);
+        for triangle in indices.chunks_exact(3) {
+            let normal = vertices.normals[triangle[0]];
+            assert!(normal.abs().max_element() > 0.9999);
+            assert!(triangle.iter().all(|index| vertices.normals[*index] == normal));
+        }
+    }
+
+    #[test]
+    fn angle_weighting_ignores_how_faces_are_split() {
+        //A corner where one side is split into two triangles and the other isn't
+        let positions = vec![Vec3::ZERO, Vec3::X, Vec3::new(1.0, 0.0, -1.0), Vec3::new(0.0, 0.0, -1.0), Vec3::Z, Vec3::NEG_Y];
+        let mut indices = vec![0, 1, 2, 0, 2, 3, 0, 4, 5];
+        let build = |positions: &Vec<Vec3>| VertexInput { colours: vec![Vec3::ONE; 6], uvs: vec![Vec2::ZERO; 6], positions: positions.clone(), ..Default::default() };
+
+        let mut angle = build(&positions);
+        smooth_normals(&mut angle, &mut indices, SmoothNormals::default());
+        let mut area = build(&positions);
+        smooth_normals(&mut area, &mut indices, SmoothNormals { weighting: NormalWeighting::Area, ..Default::default() });
+
+        //The split side covers 90 degrees against 90 for the other, by area it's 1 against 0.5
+        let expected_angle = (Vec3::Y + Vec3::X).normalize();
+        let expected_area = (Vec3::Y + Vec3::X * 0.5).normalize();
+        assert!(angle.normals[0].abs_diff_eq(expected_angle, 1e-5), "{:?}", angle.normals[0]);
+        assert!(area.normals[0].abs_diff_eq(expected_area, 1e-5), "{:?}", area.normals[0]);
+    }
+
+    #[test]
+    fn flat_normals_and_tangents() {
+        let (mut vertices, mut indices) = cube();
+        vertices.uvs = vertices.positions.iter().map(|p| Vec2::new(p.x + p.z, p.y)).collect();
+        flat_normals(&mut vertices, &mut indices);
+
+        assert_eq!(vertices.len(), 36);
+        assert_eq!(indices, (0..36).collect::<Vec<_>>());
+        assert!(vertices.normals.iter().all(|n| n.abs().max_element() == 1.0));
+
+        generate_tangents(&mut vertices, &indices);
+        for (tangent, normal) in vertices.tangents.iter().zip(&vertices.normals) {
+            assert!(tangent.truncate().is_normalized());
+            assert!(tangent.truncate().dot(*normal).abs() < 1e-5);
+            assert!(tangent.w.abs() == 1.0);
+        }
+    }
+}
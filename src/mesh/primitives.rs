@@ -0,0 +1,451 @@
+//Generators for common shapes, centered on the origin with y up.
+//Every vertex has a normal and uvs with v going up, colours are white
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::f32::consts::TAU;
+
+use glam::UVec2;
+use glam::Vec2;
+use glam::Vec3;
+
+use crate::renderer::data::VertexInput;
+use super::position_key;
+
+#[derive(Default)]
+struct Builder {
+    vertices: VertexInput,
+    indices: Vec<usize>
+}
+
+impl Builder {
+
+    fn vertex(&mut self, position: Vec3, normal: Vec3, uv: Vec2) -> usize {
+        self.vertices.positions.push(position);
+        self.vertices.normals.push(normal);
+        self.vertices.uvs.push(uv);
+        self.vertices.colours.push(Vec3::ONE);
+        self.vertices.len() - 1
+    }
+
+    //Wound counter clockwise seen from the side the normals point to, triangles without area are dropped
+    fn triangle(&mut self, a: usize, b: usize, c: usize) {
+        let [pa, pb, pc] = [a, b, c].map(|i| self.vertices.positions[i]);
+        let cross = (pb - pa).cross(pc - pa);
+
+        let longest = (pb - pa).length_squared().max((pc - pa).length_squared()).max((pc - pb).length_squared());
+        if cross.length() <= longest * 1e-6 { return; }
+
+        let normal = self.vertices.normals[a] + self.vertices.normals[b] + self.vertices.normals[c];
+        if cross.dot(normal) >= 0.0 {
+            self.indices.extend_from_slice(&[a, b, c]);
+        } else {
+            self.indices.extend_from_slice(&[a, c, b]);
+        }
+    }
+
+    //Grid of (columns + 1) x (rows + 1) vertices from f(column, row)
+    fn surface(&mut self, columns: u32, rows: u32, mut f: impl FnMut(u32, u32) -> (Vec3, Vec3, Vec2)) {
+        let first = self.vertices.len();
+
+        for row in 0..=rows {
+            for column in 0..=columns {
+                let (position, normal, uv) = f(column, row);
+                self.vertex(position, normal, uv);
+            }
+        }
+
+        let at = |column: u32, row: u32| first + (row * (columns + 1) + column) as usize;
+        for row in 0..rows {
+            for column in 0..columns {
+                self.triangle(at(column, row), at(column + 1, row), at(column + 1, row + 1));
+                self.triangle(at(column + 1, row + 1), at(column, row + 1), at(column, row));
+            }
+        }
+    }
+
+    //Flat circle facing +y or -y
+    fn disc(&mut self, y: f32, radius: f32, facing_up: bool, segments: u32) {
+        let normal = if facing_up { Vec3::Y } else { Vec3::NEG_Y };
+        let center = self.vertex(Vec3::new(0.0, y, 0.0), normal, Vec2::splat(0.5));
+
+        //Seen from the side it faces, u goes along +x and v along -z on top and +z below
+        let ring: Vec<usize> = (0..=segments).map(|i| {
+            let direction = around_y(i, segments);
+            let uv = Vec2::new(direction.x, if facing_up { -direction.z } else { direction.z }) * 0.5 + 0.5;
+            self.vertex(direction * radius + Vec3::Y * y, normal, uv)
+        }).collect();
+
+        for pair in ring.windows(2) {
+            self.triangle(center, pair[0], pair[1]);
+        }
+    }
+
+    fn finish(self) -> (VertexInput, Vec<usize>) {
+        (self.vertices, self.indices)
+    }
+}
+
+fn fraction(i: u32, count: u32) -> f32 { i as f32 / count as f32 }
+
+//Step i of count on a circle around y starting at +z, counter clockwise seen from above.
+//The last step lands exactly on the first so seams share positions
+fn around_y(i: u32, count: u32) -> Vec3 {
+    let angle = fraction(i % count, count) * TAU;
+    Vec3::new(angle.sin(), 0.0, angle.cos())
+}
+
+//Unit direction at a latitude in radians, poles are snapped so all of their vertices share a position
+fn on_sphere(around: Vec3, latitude: f32) -> Vec3 {
+    if latitude.abs() >= PI * 0.5 { return Vec3::Y * latitude.signum(); }
+    around * latitude.cos() + Vec3::Y * latitude.sin()
+}
+
+//Part of a flat polygon on one side of the x = 0 plane, points on the plane belong to both sides.
+//Cuts are made from the -x end of an edge so polygons sharing the edge get the same point
+fn clip_to_side(polygon: &[Vec3], side: f32) -> Vec<Vec3> {
+    let mut out = Vec::with_capacity(polygon.len() + 1);
+
+    for (k, a) in polygon.iter().enumerate() {
+        let b = polygon[(k + 1) % polygon.len()];
+        if a.x * side >= 0.0 { out.push(*a); }
+
+        if (a.x < 0.0 && b.x > 0.0) || (a.x > 0.0 && b.x < 0.0) {
+            let (low, high) = if a.x < b.x { (*a, b) } else { (b, *a) };
+            let cut = low + (high - low) * (low.x / (low.x - high.x));
+            out.push(Vec3::new(0.0, cut.y, cut.z));
+        }
+    }
+
+    out
+}
+
+//On the XZ plane facing +y, u along +x and v along -z
+pub fn plane(size: Vec2, subdivisions: UVec2) -> (VertexInput, Vec<usize>) {
+    let subdivisions = subdivisions.max(UVec2::ONE);
+    let mut builder = Builder::default();
+
+    builder.surface(subdivisions.x, subdivisions.y, |column, row| {
+        let uv = Vec2::new(fraction(column, subdivisions.x), fraction(row, subdivisions.y));
+        (Vec3::new((uv.x - 0.5) * size.x, 0.0, (0.5 - uv.y) * size.y), Vec3::Y, uv)
+    });
+
+    builder.finish()
+}
+
+//Every face has its own vertices and the whole texture, split into segments x segments quads
+pub fn cube(size: Vec3, segments: u32) -> (VertexInput, Vec<usize>) {
+    let segments = segments.max(1);
+    let mut builder = Builder::default();
+
+    //Normal and up of each face, right is up x normal
+    let faces = [
+        (Vec3::Z, Vec3::Y), (Vec3::X, Vec3::Y), (Vec3::NEG_Z, Vec3::Y),
+        (Vec3::NEG_X, Vec3::Y), (Vec3::Y, Vec3::NEG_Z), (Vec3::NEG_Y, Vec3::Z)
+    ];
+
+    for (normal, up) in faces {
+        let right = up.cross(normal);
+        builder.surface(segments, segments, |column, row| {
+            let uv = Vec2::new(fraction(column, segments), fraction(row, segments));
+            let position = (normal * 0.5 + right * (uv.x - 0.5) + up * (uv.y - 0.5)) * size;
+            (position, normal, uv)
+        });
+    }
+
+    builder.finish()
+}
+
+//Latitude and longitude grid, u wraps around y and v goes from the bottom pole to the top
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> (VertexInput, Vec<usize>) {
+    let (segments, rings) = (segments.max(3), rings.max(2));
+    let mut builder = Builder::default();
+
+    builder.surface(segments, rings, |column, row| {
+        let uv = Vec2::new(fraction(column, segments), fraction(row, rings));
+        let normal = on_sphere(around_y(column, segments), (uv.y - 0.5) * PI);
+        (normal * radius, normal, uv)
+    });
+
+    builder.finish()
+}
+
+//Subdivided icosahedron, evenly spaced vertices. Triangles are cut along the u seam and vertices split at the poles
+pub fn icosphere(radius: f32, subdivisions: u32) -> (VertexInput, Vec<usize>) {
+
+    let t = (1.0 + 5.0f32.sqrt()) * 0.5;
+    let mut positions: Vec<Vec3> = [
+        (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+        (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+        (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0)
+    ].iter().map(|(x, y, z)| Vec3::new(*x, *y, *z).normalize()).collect();
+
+    let mut triangles: Vec<[usize; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1]
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut midpoint = |a: usize, b: usize| -> usize {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                positions.push((positions[a] + positions[b]).normalize());
+                positions.len() - 1
+            })
+        };
+
+        triangles = triangles.iter().flat_map(|[a, b, c]| {
+            let (ab, bc, ca) = (midpoint(*a, *b), midpoint(*b, *c), midpoint(*c, *a));
+            [[*a, ab, ca], [*b, bc, ab], [*c, ca, bc], [ab, bc, ca]]
+        }).collect();
+    }
+
+    let uv_of = |p: Vec3| Vec2::new(p.x.atan2(p.z) / TAU + 0.5, p.y.clamp(-1.0, 1.0).asin() / PI + 0.5);
+    let is_pole = |p: Vec3| p.x.abs() < 1e-6 && p.z.abs() < 1e-6;
+    let on_seam = |p: Vec3| p.x == 0.0 && p.z < 0.0;
+
+    let mut builder = Builder::default();
+    let mut shared: HashMap<([u32; 3], u32), usize> = HashMap::new();
+
+    for triangle in triangles {
+        let corners = triangle.map(|i| positions[i]);
+
+        //Triangles across the x = 0 plane are cut along it, all of them so neighbours agree on the cuts.
+        //At the back that's the seam where u goes from 1 back to 0, there the -x side gets u 0
+        //on the seam and the +x side u 1, so u stays within 0-1
+        let (min_u, max_u) = corners.iter().filter(|p| !is_pole(**p))
+            .fold((f32::MAX, f32::MIN), |(low, high), p| (low.min(uv_of(*p).x), high.max(uv_of(*p).x)));
+        let wraps = max_u - min_u > 0.5;
+        let crosses = corners.iter().any(|p| p.x < 0.0) && corners.iter().any(|p| p.x > 0.0);
+
+        let pieces = if wraps || crosses {
+            let seam = |u: f32| if wraps { Some(u) } else { None };
+            vec![(clip_to_side(&corners, -1.0), seam(0.0)), (clip_to_side(&corners, 1.0), seam(1.0))]
+        } else {
+            vec![(corners.to_vec(), None)]
+        };
+
+        for (polygon, seam_u) in pieces {
+            for k in 1..polygon.len().saturating_sub(1) {
+                let points = [polygon[0], polygon[k], polygon[k + 1]];
+                let uvs = points.map(|p| {
+                    let uv = uv_of(p.normalize());
+                    match seam_u {
+                        Some(u) if on_seam(p) => Vec2::new(u, uv.y),
+                        _ => uv
+                    }
+                });
+
+                //Poles have no u of their own, they take the middle of the other two
+                let corners: Vec<usize> = (0..3).map(|k| {
+                    let (position, normal) = (points[k] * radius, points[k].normalize());
+                    if is_pole(points[k]) {
+                        let u = (uvs[(k + 1) % 3].x + uvs[(k + 2) % 3].x) * 0.5;
+                        builder.vertex(position, normal, Vec2::new(u, uvs[k].y))
+                    } else {
+                        *shared.entry((position_key(points[k]), uvs[k].x.to_bits())).or_insert_with(|| builder.vertex(position, normal, uvs[k]))
+                    }
+                }).collect();
+
+                builder.triangle(corners[0], corners[1], corners[2]);
+            }
+        }
+    }
+
+    builder.finish()
+}
+
+//Side u wraps around y and v goes up, the caps map the texture onto the circle
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> (VertexInput, Vec<usize>) {
+    let segments = segments.max(3);
+    let mut builder = Builder::default();
+
+    builder.surface(segments, 1, |column, row| {
+        let uv = Vec2::new(fraction(column, segments), row as f32);
+        let normal = around_y(column, segments);
+        (normal * radius + Vec3::Y * (uv.y - 0.5) * height, normal, uv)
+    });
+
+    builder.disc(height * 0.5, radius, true, segments);
+    builder.disc(-height * 0.5, radius, false, segments);
+
+    builder.finish()
+}
+
+//Base at -height / 2, the tip is split per segment so each keeps its own normal and u
+pub fn cone(radius: f32, height: f32, segments: u32) -> (VertexInput, Vec<usize>) {
+    let segments = segments.max(3);
+    let mut builder = Builder::default();
+
+    builder.surface(segments, 1, |column, row| {
+        let uv = Vec2::new(fraction(column, segments), row as f32);
+        let around = around_y(column, segments);
+        let normal = (around * height + Vec3::Y * radius).normalize();
+        (around * radius * (1.0 - uv.y) + Vec3::Y * (uv.y - 0.5) * height, normal, uv)
+    });
+
+    builder.disc(-height * 0.5, radius, false, segments);
+
+    builder.finish()
+}
+
+//Ring around y, u goes around y and v around the tube starting on the outside
+pub fn torus(major_radius: f32, minor_radius: f32, major_segments: u32, minor_segments: u32) -> (VertexInput, Vec<usize>) {
+    let (major_segments, minor_segments) = (major_segments.max(3), minor_segments.max(3));
+    let mut builder = Builder::default();
+
+    builder.surface(major_segments, minor_segments, |column, row| {
+        let uv = Vec2::new(fraction(column, major_segments), fraction(row, minor_segments));
+        let around = around_y(column, major_segments);
+        let tube = Vec2::from_angle(fraction(row % minor_segments, minor_segments) * TAU);
+        let normal = around * tube.x + Vec3::Y * tube.y;
+        (around * major_radius + normal * minor_radius, normal, uv)
+    });
+
+    builder.finish()
+}
+
+//Cylinder of height with hemispheres on both ends, rings per hemisphere. v follows the length of the profile
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> (VertexInput, Vec<usize>) {
+    let (segments, rings) = (segments.max(3), rings.max(1));
+    let mut builder = Builder::default();
+
+    let profile_length = PI * radius + height;
+
+    //Row rings sits at the bottom of the cylinder and rings + 1 at the top
+    builder.surface(segments, rings * 2 + 1, |column, row| {
+        let (latitude, offset, arc) = if row <= rings {
+            let latitude = (fraction(row, rings) - 1.0) * PI * 0.5;
+            (latitude, -height * 0.5, (latitude + PI * 0.5) * radius)
+        } else {
+            let latitude = fraction(row - rings - 1, rings) * PI * 0.5;
+            (latitude, height * 0.5, (latitude + PI * 0.5) * radius + height)
+        };
+
+        let normal = on_sphere(around_y(column, segments), latitude);
+        let uv = Vec2::new(fraction(column, segments), arc / profile_length);
+        (normal * radius + Vec3::Y * offset, normal, uv)
+    });
+
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use glam::{UVec2, Vec2, Vec3};
+
+    use super::{capsule, cone, cube, cylinder, icosphere, plane, torus, uv_sphere};
+    use crate::mesh::position_key;
+    use crate::renderer::data::VertexInput;
+
+    fn area(vertices: &VertexInput, indices: &[usize]) -> f32 {
+        indices.chunks_exact(3).map(|t| {
+            let [a, b, c] = [vertices.positions[t[0]], vertices.positions[t[1]], vertices.positions[t[2]]];
+            (b - a).cross(c - a).length() * 0.5
+        }).sum()
+    }
+
+    //Counting by position, every edge of a closed surface has exactly two triangles, in opposite directions
+    fn assert_closed(name: &str, vertices: &VertexInput, indices: &[usize]) {
+        let mut edges: HashMap<([u32; 3], [u32; 3]), i32> = HashMap::new();
+        for triangle in indices.chunks_exact(3) {
+            for k in 0..3 {
+                let (a, b) = (position_key(vertices.positions[triangle[k]]), position_key(vertices.positions[triangle[(k + 1) % 3]]));
+                *edges.entry((a, b)).or_default() += 1;
+            }
+        }
+
+        for ((a, b), count) in &edges {
+            assert_eq!(*count, 1, "{}: edge used {} times", name, count);
+            assert_eq!(edges.get(&(*b, *a)), Some(&1), "{}: open edge", name);
+        }
+    }
+
+    //Faces point away from the given inside point, normals agree with the faces
+    fn assert_outward(name: &str, vertices: &VertexInput, indices: &[usize], inside: impl Fn(Vec3) -> Vec3) {
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [vertices.positions[triangle[0]], vertices.positions[triangle[1]], vertices.positions[triangle[2]]];
+            let face = (b - a).cross(c - a);
+            let center = (a + b + c) / 3.0;
+
+            assert!(face.dot(center - inside(center)) > 0.0, "{}: face at {:?} points inwards", name, center);
+            for index in triangle {
+                assert!(vertices.normals[*index].is_normalized(), "{}", name);
+                assert!(vertices.normals[*index].dot(face) > 0.0, "{}: normal against face at {:?}", name, center);
+            }
+        }
+    }
+
+    #[test]
+    fn flat_shapes() {
+        let (vertices, indices) = plane(Vec2::new(2.0, 4.0), UVec2::new(4, 8));
+        assert_eq!((vertices.len(), indices.len()), (5 * 9, 4 * 8 * 6));
+        assert!((area(&vertices, &indices) - 8.0).abs() < 1e-4);
+        assert_outward("plane", &vertices, &indices, |p| p - Vec3::Y);
+
+        let (vertices, indices) = cube(Vec3::new(1.0, 2.0, 3.0), 2);
+        assert_eq!((vertices.len(), indices.len()), (6 * 9, 6 * 8 * 3));
+        assert!((area(&vertices, &indices) - 22.0).abs() < 1e-4);
+        assert_closed("cube", &vertices, &indices);
+        assert_outward("cube", &vertices, &indices, |_| Vec3::ZERO);
+    }
+
+    #[test]
+    fn round_shapes() {
+        let shapes = [
+            ("uv sphere", uv_sphere(1.0, 32, 16), 4.0 * std::f32::consts::PI),
+            ("icosphere", icosphere(1.0, 3), 4.0 * std::f32::consts::PI),
+            ("cylinder", cylinder(1.0, 2.0, 32), 6.0 * std::f32::consts::PI),
+            ("cone", cone(1.0, 1.0, 32), std::f32::consts::PI * (1.0 + 2.0f32.sqrt())),
+            ("capsule", capsule(0.5, 1.0, 32, 8), std::f32::consts::PI * 2.0)
+        ];
+
+        for (name, (vertices, indices), expected_area) in &shapes {
+            let area = area(vertices, indices);
+            assert!((area - expected_area).abs() < expected_area * 0.02, "{}: area {} vs {}", name, area, expected_area);
+            assert!(vertices.uvs.iter().all(|uv| uv.cmpge(Vec2::ZERO).all() && uv.cmple(Vec2::ONE).all()), "{}", name);
+            assert_closed(name, vertices, indices);
+            assert_outward(name, vertices, indices, |_| Vec3::ZERO);
+        }
+    }
+
+    #[test]
+    fn icosphere_uvs_do_not_wrap_across_triangles() {
+        let (vertices, indices) = icosphere(2.0, 2);
+        //Triangles crossing x = 0 are cut in two or three
+        assert!(indices.len() > 20 * 16 * 3);
+
+        for triangle in indices.chunks_exact(3) {
+            let us: Vec<f32> = triangle.iter().map(|i| vertices.uvs[*i].x).collect();
+            let spread = us.iter().cloned().fold(f32::MIN, f32::max) - us.iter().cloned().fold(f32::MAX, f32::min);
+            assert!(spread < 0.3, "{:?}", us);
+        }
+
+        //Cut points lie on the faces, in the seam plane, everything else on the sphere
+        for (position, uv) in vertices.positions.iter().zip(&vertices.uvs) {
+            assert!((position.length() - 2.0).abs() < 1e-5 || (position.x == 0.0 && position.length() < 2.0), "{:?}", position);
+            if position.x == 0.0 && position.z < 0.0 { assert!(uv.x == 0.0 || uv.x == 1.0); }
+        }
+
+        for subdivisions in 0..4 {
+            let (vertices, indices) = icosphere(1.0, subdivisions);
+            assert_closed("icosphere", &vertices, &indices);
+            assert!(vertices.uvs.iter().all(|uv| uv.cmpge(Vec2::ZERO).all() && uv.cmple(Vec2::ONE).all()), "{}", subdivisions);
+        }
+    }
+
+    #[test]
+    fn torus_faces_point_away_from_the_tube() {
+        let (vertices, indices) = torus(1.0, 0.25, 32, 16);
+        assert_eq!(vertices.len(), 33 * 17);
+
+        let expected_area = 4.0 * std::f32::consts::PI * std::f32::consts::PI * 0.25;
+        assert!((area(&vertices, &indices) - expected_area).abs() < expected_area * 0.02);
+        assert_closed("torus", &vertices, &indices);
+        assert_outward("torus", &vertices, &indices, |p| (p * Vec3::new(1.0, 0.0, 1.0)).normalize());
+    }
+}
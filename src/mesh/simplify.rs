@@ -0,0 +1,446 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+
+use glam::DMat4;
+use glam::DVec3;
+use glam::Vec3;
+
+use crate::renderer::data::VertexInput;
+use super::position_key;
+use super::remove_unused;
+
+//Border planes count for this much more than faces so outlines hold their shape
+const BORDER_WEIGHT: f64 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimplifyOptions {
+    //Stops once no more than this many triangles are left
+    pub target_triangles: usize,
+    //Largest error a single collapse may add, roughly a distance in object space
+    pub max_error: f32
+}
+
+impl Default for SimplifyOptions {
+    fn default() -> Self {
+        Self { target_triangles: 0, max_error: f32::INFINITY }
+    }
+}
+
+pub struct Simplified {
+    pub vertices: VertexInput,
+    pub indices: Vec<usize>,
+    //Largest error of any collapse made
+    pub error: f32
+}
+
+//Sum of squared distances to a set of planes
+#[derive(Debug, Clone, Copy)]
+struct Quadric(DMat4);
+
+impl Quadric {
+    fn from_plane(normal: DVec3, point: DVec3, weight: f64) -> Self {
+        let plane = normal.extend(-normal.dot(point));
+        Quadric(DMat4::from_cols(plane * plane.x, plane * plane.y, plane * plane.z, plane * plane.w) * weight)
+    }
+
+    fn error(&self, position: Vec3) -> f64 {
+        let v = position.as_dvec3().extend(1.0);
+        v.dot(self.0 * v).max(0.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Interior,
+    //Can only slide along the border
+    Border,
+    //Seams, corners and anything non-manifold never move
+    Locked
+}
+
+//Moves the position from onto to, cheapest first
+struct Collapse {
+    cost: f64,
+    from: usize,
+    to: usize,
+    versions: (u32, u32)
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+//Reversed so the BinaryHeap pops the smallest cost
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering { other.cost.total_cmp(&self.cost) }
+}
+
+//Collapse state over positions rather than vertices, so both sides of a seam move together
+struct Simplifier<'a> {
+    vertices: &'a VertexInput,
+    indices: Vec<usize>,
+    group: Vec<usize>,
+    positions: Vec<Vec3>,
+    kinds: Vec<Kind>,
+    quadrics: Vec<Quadric>,
+    //Triangles around each position, may include dead ones
+    incident: Vec<Vec<usize>>,
+    alive: Vec<bool>,
+    versions: Vec<u32>
+}
+
+impl Simplifier<'_> {
+
+    fn corners(&self, triangle: usize) -> [usize; 3] {
+        [self.group[self.indices[triangle * 3]], self.group[self.indices[triangle * 3 + 1]], self.group[self.indices[triangle * 3 + 2]]]
+    }
+
+    fn live_triangles(&self, position: usize) -> impl Iterator<Item = usize> + '_ {
+        self.incident[position].iter().copied().filter(|t| self.alive[*t])
+    }
+
+    fn neighbours(&self, position: usize) -> Vec<usize> {
+        let mut neighbours: Vec<usize> = self.live_triangles(position)
+            .flat_map(|t| self.corners(t))
+            .filter(|p| *p != position)
+            .collect();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        neighbours
+    }
+
+    fn cost(&self, from: usize, to: usize) -> f64 {
+        Quadric(self.quadrics[from].0 + self.quadrics[to].0).error(self.positions[to])
+    }
+
+    fn candidate(&self, from: usize, to: usize) -> Option<Collapse> {
+        if self.kinds[from] == Kind::Locked { return None; }
+        Some(Collapse { cost: self.cost(from, to), from, to, versions: (self.versions[from], self.versions[to]) })
+    }
+
+    //The vertex the corners of from will use after the collapse, None when it isn't allowed
+    fn check(&self, from: usize, to: usize) -> Option<usize> {
+
+        let shared: Vec<usize> = self.live_triangles(from).filter(|t| self.corners(*t).contains(&to)).collect();
+
+        //Interior edges have two sides, border vertices only move along the border
+        let expected = if self.kinds[from] == Kind::Border { 1 } else { 2 };
+        if shared.len() != expected { return None; }
+
+        //Positions next to both would end up with a duplicated edge unless they close a shared triangle
+        let to_neighbours = self.neighbours(to);
+        let common = self.neighbours(from).iter().filter(|p| to_neighbours.binary_search(p).is_ok()).count();
+        if common != shared.len() { return None; }
+
+        //Seams at to have to agree on which side from joins
+        let mut target = None;
+        for t in &shared {
+            let corner = (0..3).find(|k| self.group[self.indices[t * 3 + k]] == to)?;
+            let vertex = self.indices[t * 3 + corner];
+            if target.is_some_and(|existing| existing != vertex) { return None; }
+            target = Some(vertex);
+        }
+
+        //No triangle may flip or collapse
+        for t in self.live_triangles(from).filter(|t| !shared.contains(t)) {
+            let corners = self.corners(t);
+            let old = corners.map(|p| self.positions[p]);
+            let new = corners.map(|p| if p == from { self.positions[to] } else { self.positions[p] });
+
+            let old_normal = (old[1] - old[0]).cross(old[2] - old[0]);
+            let new_normal = (new[1] - new[0]).cross(new[2] - new[0]);
+            if new_normal.length_squared() <= old_normal.length_squared() * 1e-6 || old_normal.dot(new_normal) <= 0.0 { return None; }
+        }
+
+        target
+    }
+
+    fn collapse(&mut self, from: usize, to: usize, target: usize) -> usize {
+
+        let mut removed = 0;
+        let triangles: Vec<usize> = self.live_triangles(from).collect();
+
+        for t in triangles {
+            if self.corners(t).contains(&to) {
+                self.alive[t] = false;
+                removed += 1;
+            } else {
+                for k in 0..3 {
+                    if self.group[self.indices[t * 3 + k]] == from { self.indices[t * 3 + k] = target; }
+                }
+                self.incident[to].push(t);
+            }
+        }
+
+        self.incident[from].clear();
+        let alive = &self.alive;
+        self.incident[to].retain(|t| alive[*t]);
+
+        self.quadrics[to] = Quadric(self.quadrics[to].0 + self.quadrics[from].0);
+        self.versions[to] += 1;
+        removed
+    }
+}
+
+//Quadric error edge collapse. Vertices only ever move onto other vertices, so attributes stay exact,
+//and positions with more than one vertex (UV seams, hard edges) are never moved.
+//Weld first, a mesh with every triangle split apart is all seams
+pub fn simplify(vertices: &VertexInput, indices: &[usize], options: SimplifyOptions) -> Simplified {
+
+    let triangle_count = indices.len() / 3;
+    let indices = indices[..triangle_count * 3].to_vec();
+
+    //Positions
+    let mut group_ids: HashMap<[u32; 3], usize> = HashMap::new();
+    let group: Vec<usize> = vertices.positions.iter().map(|position| {
+        let next = group_ids.len();
+        *group_ids.entry(position_key(*position)).or_insert(next)
+    }).collect();
+
+    let position_count = group_ids.len();
+    let mut positions = vec![Vec3::ZERO; position_count];
+    for (vertex, position) in group.iter().enumerate() { positions[*position] = vertices.positions[vertex]; }
+
+    let mut simplifier = Simplifier {
+        vertices,
+        indices,
+        group,
+        positions,
+        kinds: vec![Kind::Interior; position_count],
+        quadrics: vec![Quadric(DMat4::ZERO); position_count],
+        incident: vec![Vec::new(); position_count],
+        alive: vec![true; triangle_count],
+        versions: vec![0; position_count]
+    };
+
+    //Edges by how many triangles use them, keeping one of those triangles
+    let mut edges: HashMap<(usize, usize), (u32, usize)> = HashMap::new();
+    let mut first_vertex: Vec<Option<usize>> = vec![None; position_count];
+    let mut live = 0;
+
+    for t in 0..triangle_count {
+        let corners = simplifier.corners(t);
+        if corners[0] == corners[1] || corners[1] == corners[2] || corners[2] == corners[0] {
+            simplifier.alive[t] = false;
+            continue;
+        }
+        live += 1;
+
+        for k in 0..3 {
+            let (a, b) = (corners[k], corners[(k + 1) % 3]);
+            let edge = edges.entry((a.min(b), a.max(b))).or_insert((0, t));
+            edge.0 += 1;
+
+            simplifier.incident[a].push(t);
+
+            let vertex = simplifier.indices[t * 3 + k];
+            match first_vertex[a] {
+                None => first_vertex[a] = Some(vertex),
+                Some(first) if first != vertex => simplifier.kinds[a] = Kind::Locked,
+                _ => {}
+            }
+        }
+
+        let [a, b, c] = corners.map(|p| simplifier.positions[p].as_dvec3());
+        let normal = (b - a).cross(c - a).normalize_or_zero();
+        let face = Quadric::from_plane(normal, a, 1.0);
+        for p in corners { simplifier.quadrics[p].0 += face.0; }
+    }
+
+    //Border edges get planes at right angles to their face
+    let mut border_edges = vec![0; position_count];
+    for ((a, b), (uses, t)) in &edges {
+        match uses {
+            1 => {
+                border_edges[*a] += 1;
+                border_edges[*b] += 1;
+
+                let [p0, p1, p2] = simplifier.corners(*t).map(|p| simplifier.positions[p].as_dvec3());
+                let (start, end) = (simplifier.positions[*a].as_dvec3(), simplifier.positions[*b].as_dvec3());
+                let normal = (p1 - p0).cross(p2 - p0).normalize_or_zero();
+                let border = Quadric::from_plane((end - start).cross(normal).normalize_or_zero(), start, BORDER_WEIGHT);
+
+                simplifier.quadrics[*a].0 += border.0;
+                simplifier.quadrics[*b].0 += border.0;
+            }
+            2 => {}
+            _ => {
+                simplifier.kinds[*a] = Kind::Locked;
+                simplifier.kinds[*b] = Kind::Locked;
+            }
+        }
+    }
+
+    for (kind, borders) in simplifier.kinds.iter_mut().zip(&border_edges) {
+        if *kind == Kind::Locked { continue; }
+        *kind = match borders {
+            0 => Kind::Interior,
+            2 => Kind::Border,
+            _ => Kind::Locked
+        };
+    }
+
+    let mut heap = BinaryHeap::new();
+    for from in 0..position_count {
+        for to in simplifier.neighbours(from) {
+            heap.extend(simplifier.candidate(from, to));
+        }
+    }
+
+    let max_cost = (options.max_error as f64).powi(2);
+    let mut error = 0.0f64;
+
+    while live > options.target_triangles {
+        let Some(collapse) = heap.pop() else { break; };
+        if collapse.cost > max_cost { break; }
+
+        //Stale, the positions changed since it was queued
+        let (from, to) = (collapse.from, collapse.to);
+        if collapse.versions != (simplifier.versions[from], simplifier.versions[to]) || simplifier.incident[from].is_empty() { continue; }
+
+        let Some(target) = simplifier.check(from, to) else { continue; };
+
+        live -= simplifier.collapse(from, to, target);
+        error = error.max(collapse.cost);
+
+        for neighbour in simplifier.neighbours(to) {
+            heap.extend(simplifier.candidate(to, neighbour));
+            heap.extend(simplifier.candidate(neighbour, to));
+        }
+    }
+
+    let mut indices: Vec<usize> = (0..triangle_count)
+        .filter(|t| simplifier.alive[*t])
+        .flat_map(|t| simplifier.indices[t * 3..t * 3 + 3].to_vec())
+        .collect();
+    let mut vertices = simplifier.vertices.clone();
+    remove_unused(&mut vertices, &mut indices);
+
+    Simplified { vertices, indices, error: error.sqrt() as f32 }
+}
+
+//Each level is simplified from the one before it, ratios are of the original triangle count from
+//most to least detailed. Level errors add up so they bound the distance to the original
+pub fn lod_chain(vertices: &VertexInput, indices: &[usize], ratios: &[f32]) -> Vec<Simplified> {
+
+    let triangle_count = indices.len() / 3;
+    let mut levels: Vec<Simplified> = Vec::new();
+
+    for ratio in ratios {
+        let options = SimplifyOptions { target_triangles: (triangle_count as f32 * ratio) as usize, ..Default::default() };
+
+        let level = match levels.last() {
+            Some(previous) => {
+                let level = simplify(&previous.vertices, &previous.indices, options);
+                Simplified { error: level.error + previous.error, ..level }
+            }
+            None => simplify(vertices, indices, options)
+        };
+        levels.push(level);
+    }
+
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+
+    use super::{lod_chain, simplify, SimplifyOptions};
+    use crate::renderer::data::VertexInput;
+
+    //Square grid on the XY plane with cells per side, height gives z
+    fn grid(cells: usize, height: impl Fn(Vec2) -> f32) -> (VertexInput, Vec<usize>) {
+        let side = cells + 1;
+        let uvs: Vec<Vec2> = (0..side * side).map(|i| Vec2::new((i % side) as f32, (i / side) as f32) / cells as f32).collect();
+        let positions = uvs.iter().map(|uv| uv.extend(height(*uv))).collect();
+
+        let mut indices = Vec::new();
+        for y in 0..cells {
+            for x in 0..cells {
+                let corner = y * side + x;
+                indices.extend_from_slice(&[corner, corner + 1, corner + side + 1, corner + side + 1, corner + side, corner]);
+            }
+        }
+
+        (VertexInput { colours: vec![Vec3::ONE; uvs.len()], positions, uvs, ..Default::default() }, indices)
+    }
+
+    fn area(vertices: &VertexInput, indices: &[usize]) -> f32 {
+        indices.chunks_exact(3).map(|t| {
+            let [a, b, c] = [vertices.positions[t[0]], vertices.positions[t[1]], vertices.positions[t[2]]];
+            (b - a).cross(c - a).z * 0.5
+        }).sum()
+    }
+
+    #[test]
+    fn flat_grid_collapses_to_its_corners() {
+        let (vertices, indices) = grid(8, |_| 0.0);
+        let simplified = simplify(&vertices, &indices, SimplifyOptions { target_triangles: 2, ..Default::default() });
+
+        assert_eq!(simplified.indices.len(), 6);
+        assert_eq!(simplified.vertices.len(), 4);
+        assert!(simplified.error < 1e-4);
+        assert!((area(&simplified.vertices, &simplified.indices) - 1.0).abs() < 1e-5);
+
+        //Surviving vertices keep their uvs exactly
+        for (position, uv) in simplified.vertices.positions.iter().zip(&simplified.vertices.uvs) {
+            assert_eq!(position.truncate(), *uv);
+        }
+    }
+
+    #[test]
+    fn seams_are_kept() {
+        //Split the grid along x = 0.5 with different uvs on each side
+        let (mut vertices, mut indices) = grid(8, |_| 0.0);
+        let seam: Vec<usize> = (0..vertices.len()).filter(|v| vertices.positions[*v].x == 0.5).collect();
+        let first_copy = vertices.len();
+        for vertex in &seam {
+            vertices.positions.push(vertices.positions[*vertex]);
+            vertices.colours.push(Vec3::ONE);
+            vertices.uvs.push(vertices.uvs[*vertex] + Vec2::new(5.0, 0.0));
+        }
+
+        for triangle in indices.chunks_exact_mut(3) {
+            let right = triangle.iter().map(|v| vertices.positions[*v].x).sum::<f32>() > 1.5;
+            if !right { continue; }
+
+            for vertex in triangle.iter_mut() {
+                if let Some(row) = seam.iter().position(|v| v == vertex) { *vertex = first_copy + row; }
+            }
+        }
+
+        //Corners only go once the error allows it
+        let simplified = simplify(&vertices, &indices, SimplifyOptions { target_triangles: 2, max_error: 1e-3 });
+
+        //Every seam position is still there, on both sides
+        for v in &seam {
+            let position = vertices.positions[*v];
+            let sides = simplified.vertices.positions.iter().filter(|p| **p == position).count();
+            assert_eq!(sides, 2, "{:?}", position);
+        }
+        assert!((area(&simplified.vertices, &simplified.indices) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn lod_levels_shrink_and_error_grows() {
+        let (vertices, indices) = grid(16, |uv| (uv.x * 6.0).sin() * (uv.y * 5.0).cos() * 0.2);
+        let levels = lod_chain(&vertices, &indices, &[0.5, 0.25, 0.1]);
+
+        let triangles: Vec<usize> = levels.iter().map(|level| level.indices.len() / 3).collect();
+        assert!(triangles[0] <= 256 && triangles[1] <= 128 && triangles[2] <= 51, "{:?}", triangles);
+        assert!(levels.windows(2).all(|pair| pair[0].error <= pair[1].error));
+        assert!(levels[2].error > 0.0);
+
+        //Bumps are preserved before flat areas are
+        let max_error = simplify(&vertices, &indices, SimplifyOptions { max_error: 1e-3, ..Default::default() });
+        assert!(max_error.indices.len() / 3 > 51 && max_error.error <= 1e-3);
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use glam::Vec3;
+
+use crate::renderer::data::VertexInput;
+use super::remove_unused;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WeldTolerance {
+    //Largest distance between welded positions
+    pub position: f32,
+    //Largest difference in any colour, uv, normal, tangent or weight component
+    pub attribute: f32
+}
+
+impl Default for WeldTolerance {
+    fn default() -> Self {
+        Self { position: 1e-5, attribute: 1e-3 }
+    }
+}
+
+fn attributes_match(vertices: &VertexInput, a: usize, b: usize, tolerance: f32) -> bool {
+    fn close<T: Copy>(values: &[T], a: usize, b: usize, difference: impl Fn(T, T) -> f32, tolerance: f32) -> bool {
+        values.is_empty() || difference(values[a], values[b]) <= tolerance
+    }
+
+    close(&vertices.colours, a, b, |a, b| (a - b).abs().max_element(), tolerance)
+        && close(&vertices.uvs, a, b, |a, b| (a - b).abs().max_element(), tolerance)
+        && close(&vertices.normals, a, b, |a, b| (a - b).abs().max_element(), tolerance)
+        && close(&vertices.tangents, a, b, |a, b| (a - b).abs().max_element(), tolerance)
+        && close(&vertices.weights, a, b, |a, b| (a - b).abs().max_element(), tolerance)
+        && (vertices.joints.is_empty() || vertices.joints[a] == vertices.joints[b])
+}
+
+//Merges vertices within the tolerances into the first of them, then drops the triangles that
+//collapsed and the vertices nothing uses, keeping the rest in order. Returns how many vertices were removed
+pub fn weld(vertices: &mut VertexInput, indices: &mut Vec<usize>, tolerance: WeldTolerance) -> usize {
+
+    let vertex_count = vertices.len();
+
+    //Grid of cells as big as the tolerance, so matches are at most one cell away
+    let cell_size = tolerance.position.max(f32::EPSILON);
+    let cell = |position: Vec3| (position / cell_size).floor().as_ivec3();
+    let mut grid: HashMap<glam::IVec3, Vec<usize>> = HashMap::new();
+
+    let remap: Vec<usize> = (0..vertex_count).map(|vertex| {
+        let position = vertices.positions[vertex];
+        let home = cell(position);
+
+        let mut found = None;
+        'search: for z in -1..=1 {
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    let Some(candidates) = grid.get(&(home + glam::IVec3::new(x, y, z))) else { continue; };
+                    found = candidates.iter().copied().find(|other| {
+                        vertices.positions[*other].distance(position) <= tolerance.position
+                            && attributes_match(vertices, *other, vertex, tolerance.attribute)
+                    });
+                    if found.is_some() { break 'search; }
+                }
+            }
+        }
+
+        found.unwrap_or_else(|| {
+            grid.entry(home).or_default().push(vertex);
+            vertex
+        })
+    }).collect();
+
+    //Triangles that lost an edge
+    let mut welded = Vec::with_capacity(indices.len());
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [remap[triangle[0]], remap[triangle[1]], remap[triangle[2]]];
+        if a != b && b != c && c != a { welded.extend_from_slice(&[a, b, c]); }
+    }
+
+    //Merged vertices are no longer used either
+    *indices = welded;
+    remove_unused(vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+
+    use super::{weld, WeldTolerance};
+    use crate::renderer::data::VertexInput;
+
+    #[test]
+    fn duplicates_merge_unless_attributes_differ() {
+        //Two triangles of a quad with the shared edge duplicated, and slightly off on one end
+        let positions = vec![
+            Vec3::ZERO, Vec3::X, Vec3::Y,
+            Vec3::Y + Vec3::splat(1e-6), Vec3::X, Vec3::new(1.0, 1.0, 0.0)
+        ];
+        let mut uvs = vec![Vec2::ZERO; 6];
+        let mut vertices = VertexInput { colours: vec![Vec3::ONE; 6], uvs: uvs.clone(), positions: positions.clone(), ..Default::default() };
+        let mut indices = vec![0, 1, 2, 3, 4, 5];
+
+        assert_eq!(weld(&mut vertices, &mut indices, WeldTolerance::default()), 2);
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices, [0, 1, 2, 2, 1, 3]);
+
+        //A uv seam along the shared edge keeps both sides
+        uvs[4] = Vec2::ONE;
+        let mut vertices = VertexInput { colours: vec![Vec3::ONE; 6], uvs, positions, ..Default::default() };
+        let mut indices = vec![0, 1, 2, 3, 4, 5];
+        assert_eq!(weld(&mut vertices, &mut indices, WeldTolerance::default()), 1);
+        assert_eq!(indices, [0, 1, 2, 2, 3, 4]);
+    }
+
+    #[test]
+    fn collapsed_triangles_and_unused_vertices_are_removed() {
+        let positions = vec![Vec3::ZERO, Vec3::X, Vec3::new(1.0, 0.05, 0.0), Vec3::Y, Vec3::splat(9.0)];
+        let mut vertices = VertexInput { colours: vec![Vec3::ONE; 5], uvs: vec![Vec2::ZERO; 5], positions, ..Default::default() };
+        let mut indices = vec![0, 1, 2, 0, 2, 3];
+
+        let removed = weld(&mut vertices, &mut indices, WeldTolerance { position: 0.1, ..Default::default() });
+        assert_eq!(removed, 2);
+        assert_eq!(indices, [0, 1, 2]);
+        assert_eq!(vertices.positions, [Vec3::ZERO, Vec3::X, Vec3::Y]);
+    }
+}
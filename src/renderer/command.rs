@@ -0,0 +1,366 @@
+use std::rc::Rc;
+
+use glam::Mat4;
+
+use crate::texture::RenderTarget;
+use crate::texture::Sampler;
+use crate::texture::Texture;
+use super::RenderTargets;
+use super::data::InstanceData;
+use super::data::Mesh;
+use super::data::Topology;
+use super::fragment::FragmentShader;
+use super::fragment::ShadingFn;
+use super::state::BlendState;
+use super::state::DepthState;
+use super::state::PointState;
+use super::state::RasterState;
+use super::state::Viewport;
+use super::vertex::VertexCacheStats;
+use super::vertex::VertexShader;
+
+//Everything that configures the fixed function vertex and fragment shaders.
+//Not comparable since shading is a function, draws are grouped by the Rc instead
+#[derive(Debug, Default, Clone)]
+pub struct PipelineState {
+    pub raster: RasterState,
+    pub depth: DepthState,
+    pub blend: BlendState,
+    pub point: PointState,
+    pub shading: Option<ShadingFn>
+}
+
+#[derive(Clone)]
+pub enum Command {
+    BindPipeline(Rc<PipelineState>),
+    BindTexture { texture: Rc<Texture>, sampler: Sampler },
+    SetUniforms { view: Mat4, projection: Mat4 },
+    //Skins the draws after it whose vertices have joints, empty turns skinning off.
+    //Culling still uses the bind pose bounds, so skinned meshes need bounds that fit every pose
+    SetJointMatrices(Rc<[Mat4]>),
+    //None covers the whole target
+    SetViewport(Option<Viewport>),
+    //Colour is RGBA
+    Clear { colour: Option<glam::Vec4>, depth: Option<f32> },
+    DrawIndexed { mesh: Rc<Mesh>, instances: Vec<InstanceData> }
+}
+
+impl Command {
+    //Commands that draws can't be reordered across
+    fn is_barrier(&self) -> bool {
+        matches!(self, Command::SetViewport(_) | Command::Clear { .. })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DrawStats {
+    pub draw_calls: usize,
+    //Before and after culling and clipping
+    pub input_primitives: usize,
+    pub rasterized_primitives: usize,
+    //Instances skipped by the HiZBuffer before reaching the vertex shader
+    pub occluded_instances: usize,
+    pub vertex_cache: VertexCacheStats
+}
+
+//Recorded list of commands, can be submitted any number of times
+#[derive(Default, Clone)]
+pub struct CommandBuffer {
+    commands: Vec<Command>
+}
+
+//A draw together with all the state it was recorded with
+struct DrawPacket {
+    pipeline: Rc<PipelineState>,
+    texture: (Rc<Texture>, Sampler),
+    uniforms: (Mat4, Mat4),
+    joint_matrices: Rc<[Mat4]>,
+    draw: Command
+}
+
+impl CommandBuffer {
+
+    pub fn commands(&self) -> &[Command] { &self.commands }
+    pub fn len(&self) -> usize { self.commands.len() }
+    pub fn is_empty(&self) -> bool { self.commands.is_empty() }
+
+    pub fn reset(&mut self) { self.commands.clear(); }
+
+    pub fn push(&mut self, command: Command) { self.commands.push(command); }
+
+    pub fn bind_pipeline(&mut self, pipeline: Rc<PipelineState>) {
+        self.commands.push(Command::BindPipeline(pipeline));
+    }
+
+    pub fn bind_texture(&mut self, texture: Rc<Texture>, sampler: Sampler) {
+        self.commands.push(Command::BindTexture { texture, sampler });
+    }
+
+    pub fn set_uniforms(&mut self, view: Mat4, projection: Mat4) {
+        self.commands.push(Command::SetUniforms { view, projection });
+    }
+
+    pub fn set_joint_matrices(&mut self, joint_matrices: Rc<[Mat4]>) {
+        self.commands.push(Command::SetJointMatrices(joint_matrices));
+    }
+
+    pub fn set_viewport(&mut self, viewport: Option<Viewport>) {
+        self.commands.push(Command::SetViewport(viewport));
+    }
+
+    pub fn clear(&mut self, colour: Option<glam::Vec4>, depth: Option<f32>) {
+        self.commands.push(Command::Clear { colour, depth });
+    }
+
+    pub fn draw_indexed(&mut self, mesh: Rc<Mesh>, model: Mat4) {
+        self.draw_indexed_instanced(mesh, vec![InstanceData::from_model(model)]);
+    }
+
+    pub fn draw_indexed_instanced(&mut self, mesh: Rc<Mesh>, instances: Vec<InstanceData>) {
+        self.commands.push(Command::DrawIndexed { mesh, instances });
+    }
+
+    //Sorts opaque draws by pipeline and texture to minimise state changes.
+    //Blended draws keep their recorded order and go after the opaque ones,
+    //nothing is moved across a clear or viewport change.
+    pub fn sort_draws(&mut self) {
+
+        let default_pipeline = Rc::new(PipelineState::default());
+        let default_texture = (Rc::new(Texture::solid(0xFFFFFFFF)), Sampler::default());
+
+        //Resolve the state each draw was recorded with
+        let mut pipeline = default_pipeline;
+        let mut texture = default_texture;
+        let mut uniforms = (Mat4::IDENTITY, Mat4::IDENTITY);
+        let mut joint_matrices: Rc<[Mat4]> = Rc::from([]);
+
+        let mut segments: Vec<(Vec<DrawPacket>, Option<Command>)> = vec![(Vec::new(), None)];
+
+        for command in self.commands.drain(..) {
+            match command {
+                Command::BindPipeline(p) => pipeline = p,
+                Command::BindTexture { texture: t, sampler } => texture = (t, sampler),
+                Command::SetUniforms { view, projection } => uniforms = (view, projection),
+                Command::SetJointMatrices(joints) => joint_matrices = joints,
+                Command::DrawIndexed { .. } => {
+                    let packet = DrawPacket { pipeline: pipeline.clone(), texture: texture.clone(), uniforms, joint_matrices: joint_matrices.clone(), draw: command };
+                    segments.last_mut().unwrap().0.push(packet);
+                }
+                barrier => {
+                    debug_assert!(barrier.is_barrier());
+                    segments.last_mut().unwrap().1 = Some(barrier);
+                    segments.push((Vec::new(), None));
+                }
+            }
+        }
+
+        //Re-emit, only binding state that changed
+        let mut bound_pipeline: Option<Rc<PipelineState>> = None;
+        let mut bound_texture: Option<(Rc<Texture>, Sampler)> = None;
+        let mut bound_uniforms = None;
+        let mut bound_joint_matrices: Option<Rc<[Mat4]>> = None;
+
+        for (mut packets, barrier) in segments {
+
+            packets.sort_by_key(|packet| {
+                if packet.pipeline.blend.is_opaque() {
+                    (false, Rc::as_ptr(&packet.pipeline) as usize, Rc::as_ptr(&packet.texture.0) as usize)
+                } else {
+                    (true, 0, 0)
+                }
+            });
+
+            for packet in packets {
+                if bound_pipeline.as_ref().is_none_or(|p| !Rc::ptr_eq(p, &packet.pipeline)) {
+                    self.commands.push(Command::BindPipeline(packet.pipeline.clone()));
+                    bound_pipeline = Some(packet.pipeline);
+                }
+
+                if bound_texture.as_ref().is_none_or(|(t, s)| !Rc::ptr_eq(t, &packet.texture.0) || *s != packet.texture.1) {
+                    self.commands.push(Command::BindTexture { texture: packet.texture.0.clone(), sampler: packet.texture.1 });
+                    bound_texture = Some(packet.texture);
+                }
+
+                if bound_uniforms != Some(packet.uniforms) {
+                    self.commands.push(Command::SetUniforms { view: packet.uniforms.0, projection: packet.uniforms.1 });
+                    bound_uniforms = Some(packet.uniforms);
+                }
+
+                //Nothing bound yet is the same as an empty palette
+                let joints_changed = match &bound_joint_matrices {
+                    Some(bound) => !(Rc::ptr_eq(bound, &packet.joint_matrices) || (bound.is_empty() && packet.joint_matrices.is_empty())),
+                    None => !packet.joint_matrices.is_empty()
+                };
+                if joints_changed {
+                    self.commands.push(Command::SetJointMatrices(packet.joint_matrices.clone()));
+                    bound_joint_matrices = Some(packet.joint_matrices);
+                }
+
+                self.commands.push(packet.draw);
+            }
+
+            if let Some(barrier) = barrier {
+                self.commands.push(barrier);
+            }
+        }
+    }
+
+    //Executes the commands starting from default state
+    pub fn submit<T: RenderTarget>(&self, targets: &mut RenderTargets<T>) -> DrawStats {
+
+        let mut stats = DrawStats::default();
+        let mut vs = VertexShader::default();
+        let mut fs = FragmentShader::default();
+
+        for command in &self.commands {
+            match command {
+                Command::BindPipeline(pipeline) => {
+                    fs.raster = pipeline.raster;
+                    fs.depth = pipeline.depth;
+                    fs.blend = pipeline.blend;
+                    fs.point = pipeline.point;
+                    fs.shading = pipeline.shading;
+                }
+                Command::BindTexture { texture, sampler } => {
+                    fs.mesh_texture = texture.clone();
+                    fs.mesh_sampler = *sampler;
+                }
+                Command::SetUniforms { view, projection } => {
+                    vs.view = *view;
+                    vs.projection = *projection;
+                }
+                Command::SetJointMatrices(joint_matrices) => {
+                    vs.joint_matrices = joint_matrices.clone();
+                }
+                Command::SetViewport(viewport) => {
+                    fs.viewport = *viewport;
+                }
+                Command::Clear { colour, depth } => {
+                    if let Some(colour) = colour { targets.colour.clear_colour(*colour); }
+                    if let Some(depth) = depth {
+                        targets.depth.clear(*depth);
+                        if let Some(hiz) = targets.hiz.as_deref_mut() { hiz.clear(*depth); }
+                    }
+                }
+                Command::DrawIndexed { mesh, instances } => {
+                    stats.draw_calls += 1;
+
+                    //Occlusion culling against what's already been drawn
+                    let visible;
+                    let instances = match targets.hiz.as_deref() {
+                        Some(hiz) => {
+                            let viewport = fs.viewport.unwrap_or(Viewport::from_size(targets.depth.width(), targets.depth.height()));
+                            let (screen_matrix, screen_bounds) = (viewport.screen_space_matrix(), viewport.bounds(targets.depth.width(), targets.depth.height()));
+                            let vp = vs.projection * vs.view;
+
+                            visible = instances.iter()
+                                .filter(|instance| !hiz.is_occluded(&mesh.bounds.aabb, &(vp * instance.model), &screen_matrix, &screen_bounds, &fs.depth))
+                                .copied()
+                                .collect::<Vec<_>>();
+                            stats.occluded_instances += instances.len() - visible.len();
+                            &visible
+                        }
+                        None => instances
+                    };
+                    if instances.is_empty() { continue; }
+
+                    let indices = mesh.primitives();
+                    stats.input_primitives += indices.len() / mesh.topology().vertices_per_primitive() * instances.len();
+
+                    match mesh.topology() {
+                        Topology::TriangleList | Topology::TriangleStrip | Topology::TriangleFan => {
+                            let (t, i) = vs.dispatch_instanced(&mesh.vertices, indices, instances);
+                            fs.dispatch_instanced(targets.colour, targets.depth, targets.hiz.as_deref_mut(), &t, &i, instances);
+                            stats.rasterized_primitives += i.len() / 3;
+                        }
+                        Topology::LineList | Topology::LineStrip => {
+                            let (t, i) = vs.dispatch_lines_instanced(&mesh.vertices, indices, instances);
+                            fs.dispatch_lines_instanced(targets.colour, targets.depth, targets.hiz.as_deref_mut(), &t, &i, instances);
+                            stats.rasterized_primitives += i.len() / 2;
+                        }
+                        Topology::PointList => {
+                            let (t, i) = vs.dispatch_points_instanced(&mesh.vertices, indices, instances);
+                            fs.dispatch_points_instanced(targets.colour, targets.depth, targets.hiz.as_deref_mut(), &t, &i, instances);
+                            stats.rasterized_primitives += i.len();
+                        }
+                    }
+                }
+            }
+        }
+
+        stats.vertex_cache = vs.cache_stats();
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use glam::{Mat4, Vec2, Vec3, Vec4};
+
+    use super::{Command, CommandBuffer, PipelineState};
+    use crate::renderer::data::{InstanceData, Mesh, VertexInput};
+    use crate::renderer::fragment::FragmentInput;
+    use crate::renderer::state::{BlendMode, BlendState};
+    use crate::renderer::RenderTargets;
+    use crate::texture::{DepthTexture, FloatTexture, RenderTarget, Sampler, Texture};
+
+    #[test]
+    fn shading_sees_instance_data_and_ids() {
+        //Half the screen wide, one instance on each side
+        let vertices = VertexInput {
+            positions: vec![Vec3::new(-0.5, -1.0, 0.5), Vec3::new(0.5, -1.0, 0.5), Vec3::new(0.5, 1.0, 0.5), Vec3::new(-0.5, 1.0, 0.5)],
+            colours: vec![Vec3::ONE; 4],
+            uvs: vec![Vec2::ZERO; 4],
+            ..Default::default()
+        };
+        let mesh = Rc::new(Mesh::new(vertices, vec![0u16, 1, 2, 2, 3, 0]));
+        let instances = [-0.5, 0.5].map(|x| InstanceData { custom: Vec4::new(x + 1.0, 0.0, 0.0, 1.0), ..InstanceData::from_model(Mat4::from_translation(Vec3::X * x)) });
+
+        let shading = |input: &FragmentInput| input.instance.custom + Vec4::new(0.0, input.instance_id as f32, 0.0, 0.0);
+        let mut buffer = CommandBuffer::default();
+        buffer.clear(Some(Vec4::ZERO), Some(1.0));
+        buffer.bind_pipeline(Rc::new(PipelineState { shading: Some(shading), ..Default::default() }));
+        buffer.draw_indexed_instanced(mesh, instances.to_vec());
+
+        let (mut colour, mut depth) = (FloatTexture::new(4, 2), DepthTexture::new(4, 2));
+        buffer.submit(&mut RenderTargets::new(&mut colour, &mut depth));
+
+        assert_eq!(colour.read_colour(0, 0), Vec4::new(0.5, 0.0, 0.0, 1.0));
+        assert_eq!(colour.read_colour(3, 1), Vec4::new(1.5, 1.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn sort_groups_state_and_keeps_barriers() {
+        let mesh = Rc::new(Mesh::default());
+        let opaque = Rc::new(PipelineState::default());
+        let blended = Rc::new(PipelineState { blend: BlendState { mode: BlendMode::Alpha }, ..Default::default() });
+        let texture_a = Rc::new(Texture::solid(0));
+        let texture_b = Rc::new(Texture::solid(1));
+
+        let mut buffer = CommandBuffer::default();
+        buffer.clear(Some(glam::Vec4::ZERO), Some(1.0));
+        buffer.bind_pipeline(blended.clone());
+        buffer.draw_indexed(mesh.clone(), Mat4::IDENTITY);
+        buffer.bind_pipeline(opaque.clone());
+        for texture in [&texture_a, &texture_b, &texture_a] {
+            buffer.bind_texture(texture.clone(), Sampler::default());
+            buffer.draw_indexed(mesh.clone(), Mat4::IDENTITY);
+        }
+
+        buffer.sort_draws();
+        let commands = buffer.commands();
+
+        assert!(matches!(commands[0], Command::Clear { .. }));
+
+        let texture_binds = commands.iter().filter(|c| matches!(c, Command::BindTexture { .. })).count();
+        //a and b once each, plus the default texture for the blended draw
+        assert_eq!(texture_binds, 3);
+
+        //Blended draw moves to the end
+        let last_pipeline = commands.iter().rev().find_map(|c| match c { Command::BindPipeline(p) => Some(p), _ => None });
+        assert!(Rc::ptr_eq(last_pipeline.unwrap(), &blended));
+        assert_eq!(commands.iter().filter(|c| matches!(c, Command::DrawIndexed { .. })).count(), 4);
+    }
+}
@@ -0,0 +1,48 @@
+use crate::camera::Camera;
+use crate::math::bounding_volume::BoundingVolumes;
+use crate::math::plane;
+use crate::math::plane::Plane;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CullingStats {
+    pub tested: usize,
+    pub culled: usize
+}
+
+impl CullingStats {
+    pub fn visible(&self) -> usize { self.tested - self.culled }
+}
+
+//Whole-object culling against the world space camera frustum
+#[derive(Default)]
+pub struct FrustumCuller {
+    pub frustum: Vec<Plane>,
+    pub stats: CullingStats
+}
+
+impl FrustumCuller {
+
+    pub fn new(camera: &Camera) -> Self {
+        Self { frustum: camera.generate_frustum_perspective(), stats: CullingStats::default() }
+    }
+
+    //Call once per frame, resets the stats
+    pub fn update(&mut self, camera: &Camera) {
+        self.frustum = camera.generate_frustum_perspective();
+        self.stats = CullingStats::default();
+    }
+
+    //Bounds are in object space, model brings them into world space
+    pub fn is_visible(&mut self, bounds: &BoundingVolumes, model: &glam::Mat4) -> bool {
+
+        self.stats.tested += 1;
+        let world_bounds = bounds.transform(model);
+
+        //Sphere first since it is cheaper, then the tighter box test
+        let visible = plane::sphere_in_frustum(&world_bounds.sphere, &self.frustum)
+            && plane::aabb_in_frustum(&world_bounds.aabb, &self.frustum);
+
+        if !visible { self.stats.culled += 1; }
+        visible
+    }
+}
@@ -1,13 +1,21 @@
+use glam::Mat4;
 use glam::Vec2;
 use glam::Vec3;
 use glam::Vec4;
+use crate::math::bounding_volume::BoundingVolumes;
 
 //Input for vertex shader
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct VertexInput {
     pub positions: Vec<Vec3>,
     pub colours: Vec<Vec3>,
     pub uvs: Vec<Vec2>,
+    //Optional, empty when the mesh has none. Tangent w is the bitangent sign
+    pub normals: Vec<Vec3>,
+    pub tangents: Vec<Vec4>,
+    //Optional skinning influences, see animation::skin_matrix
+    pub joints: Vec<[u16; 4]>,
+    pub weights: Vec<Vec4>
 }
 
 impl VertexInput {
@@ -16,6 +24,228 @@ impl VertexInput {
     {
         [source[indices[0]].clone(), source[indices[1]].clone(), source[indices[2]].clone()]
     }
+
+    pub fn len(&self) -> usize { self.positions.len() }
+    pub fn is_empty(&self) -> bool { self.positions.is_empty() }
+    pub fn has_normals(&self) -> bool { !self.normals.is_empty() }
+    pub fn has_tangents(&self) -> bool { !self.tangents.is_empty() }
+    pub fn has_skin(&self) -> bool { !self.joints.is_empty() || !self.weights.is_empty() }
+
+    //New vertices copied from the given ones in order, repeats are allowed
+    pub fn select(&self, indices: &[usize]) -> Self {
+        fn pick<T: Copy>(source: &[T], indices: &[usize]) -> Vec<T> {
+            if source.is_empty() { Vec::new() } else { indices.iter().map(|i| source[*i]).collect() }
+        }
+
+        Self {
+            positions: pick(&self.positions, indices),
+            colours: pick(&self.colours, indices),
+            uvs: pick(&self.uvs, indices),
+            normals: pick(&self.normals, indices),
+            tangents: pick(&self.tangents, indices),
+            joints: pick(&self.joints, indices),
+            weights: pick(&self.weights, indices)
+        }
+    }
+
+    //Object space bounds, used for culling before dispatch
+    pub fn bounding_volumes(&self) -> BoundingVolumes {
+        BoundingVolumes::from_points(&self.positions)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    #[default]
+    TriangleList,
+    TriangleStrip,
+    TriangleFan,
+    LineList,
+    LineStrip,
+    PointList
+}
+
+impl Topology {
+    pub fn vertices_per_primitive(&self) -> usize {
+        match self {
+            Topology::TriangleList | Topology::TriangleStrip | Topology::TriangleFan => 3,
+            Topology::LineList | Topology::LineStrip => 2,
+            Topology::PointList => 1
+        }
+    }
+
+    //Expands the index stream into a list of vertices_per_primitive indices each.
+    //With restart, the restart index ends the current strip or fan and starts a new one
+    pub fn assemble(&self, indices: &IndexBuffer, restart: bool) -> Vec<usize> {
+
+        let count = self.vertices_per_primitive();
+        let is_list = matches!(self, Topology::TriangleList | Topology::LineList | Topology::PointList);
+
+        //Lists without restart are the stream itself, cut to whole primitives
+        if is_list && !restart {
+            return indices.iter().take(indices.len() / count * count).collect();
+        }
+
+        let restart_index = indices.restart_index();
+        let all: Vec<usize> = indices.iter().collect();
+        let mut out = Vec::with_capacity(all.len());
+
+        for segment in all.split(|index| restart && *index == restart_index) {
+            match self {
+                Topology::TriangleList | Topology::LineList | Topology::PointList => {
+                    out.extend_from_slice(&segment[..segment.len() / count * count]);
+                }
+                //Every other triangle is flipped to keep the winding
+                Topology::TriangleStrip => {
+                    for i in 0..segment.len().saturating_sub(2) {
+                        let triangle = if i % 2 == 0 { [segment[i], segment[i + 1], segment[i + 2]] } else { [segment[i + 1], segment[i], segment[i + 2]] };
+                        out.extend_from_slice(&triangle);
+                    }
+                }
+                Topology::TriangleFan => {
+                    for i in 1..segment.len().saturating_sub(1) {
+                        out.extend_from_slice(&[segment[0], segment[i], segment[i + 1]]);
+                    }
+                }
+                Topology::LineStrip => {
+                    for pair in segment.windows(2) {
+                        out.extend_from_slice(pair);
+                    }
+                }
+            }
+        }
+
+        out
+    }
+}
+
+//Compact storage for indices, the largest value of each type is the restart index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexBuffer {
+    U16(Vec<u16>),
+    U32(Vec<u32>)
+}
+
+impl Default for IndexBuffer {
+    fn default() -> Self { IndexBuffer::U16(Vec::new()) }
+}
+
+impl IndexBuffer {
+    pub fn len(&self) -> usize {
+        match self {
+            IndexBuffer::U16(indices) => indices.len(),
+            IndexBuffer::U32(indices) => indices.len()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    pub fn get(&self, i: usize) -> usize {
+        match self {
+            IndexBuffer::U16(indices) => indices[i] as usize,
+            IndexBuffer::U32(indices) => indices[i] as usize
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+
+    pub fn restart_index(&self) -> usize {
+        match self {
+            IndexBuffer::U16(_) => u16::MAX as usize,
+            IndexBuffer::U32(_) => u32::MAX as usize
+        }
+    }
+
+    pub fn size_in_bytes(&self) -> usize {
+        match self {
+            IndexBuffer::U16(indices) => std::mem::size_of_val(indices.as_slice()),
+            IndexBuffer::U32(indices) => std::mem::size_of_val(indices.as_slice())
+        }
+    }
+}
+
+impl From<Vec<u16>> for IndexBuffer {
+    fn from(indices: Vec<u16>) -> Self { IndexBuffer::U16(indices) }
+}
+
+impl From<Vec<u32>> for IndexBuffer {
+    fn from(indices: Vec<u32>) -> Self { IndexBuffer::U32(indices) }
+}
+
+//Picks the smallest type that fits, usize::MAX becomes the restart index
+impl From<Vec<usize>> for IndexBuffer {
+    fn from(indices: Vec<usize>) -> Self {
+        let largest = indices.iter().copied().filter(|index| *index != usize::MAX).max().unwrap_or(0);
+
+        if largest < u16::MAX as usize {
+            IndexBuffer::U16(indices.iter().map(|index| if *index == usize::MAX { u16::MAX } else { *index as u16 }).collect())
+        } else {
+            assert!(largest < u32::MAX as usize, "index {} doesn't fit in u32", largest);
+            IndexBuffer::U32(indices.iter().map(|index| if *index == usize::MAX { u32::MAX } else { *index as u32 }).collect())
+        }
+    }
+}
+
+//Indexed primitives with their object space bounds.
+//The index stream is assembled into a primitive list once here instead of on every draw,
+//so indices, topology and restart are only set through the constructors
+#[derive(Default)]
+pub struct Mesh {
+    pub vertices: VertexInput,
+    pub bounds: BoundingVolumes,
+    indices: IndexBuffer,
+    topology: Topology,
+    primitive_restart: bool,
+    primitives: Vec<usize>
+}
+
+impl Mesh {
+    pub fn new(vertices: VertexInput, indices: impl Into<IndexBuffer>) -> Self {
+        Self::with_topology(vertices, indices, Topology::TriangleList)
+    }
+
+    pub fn with_topology(vertices: VertexInput, indices: impl Into<IndexBuffer>, topology: Topology) -> Self {
+        let bounds = vertices.bounding_volumes();
+        let indices = indices.into();
+        let primitives = topology.assemble(&indices, false);
+        Self { vertices, bounds, indices, topology, primitive_restart: false, primitives }
+    }
+
+    pub fn with_primitive_restart(mut self) -> Self {
+        self.primitive_restart = true;
+        self.primitives = self.topology.assemble(&self.indices, true);
+        self
+    }
+
+    pub fn indices(&self) -> &IndexBuffer { &self.indices }
+    pub fn topology(&self) -> Topology { self.topology }
+    pub fn primitive_restart(&self) -> bool { self.primitive_restart }
+
+    //Indices of the primitives as a list of the topology's primitive type
+    pub fn primitives(&self) -> &[usize] { &self.primitives }
+}
+
+//Per instance data for instanced draws
+#[derive(Debug, Clone, Copy)]
+pub struct InstanceData {
+    pub model: Mat4,
+    pub tint: Vec4,
+    //Not used by the fixed function pipeline, for shading functions to read, see fragment::FragmentInput
+    pub custom: Vec4
+}
+
+impl Default for InstanceData {
+    fn default() -> Self {
+        Self { model: Mat4::IDENTITY, tint: Vec4::ONE, custom: Vec4::ZERO }
+    }
+}
+
+impl InstanceData {
+    pub fn from_model(model: Mat4) -> Self {
+        Self { model, ..Default::default() }
+    }
 }
 
 //Output for vertex shader
@@ -23,5 +253,59 @@ impl VertexInput {
 pub struct VertexOutput {
     pub ndc_positions: Vec<Vec4>,
     pub colours: Vec<Vec3>,
-    pub uvs: Vec<Vec2>
+    pub uvs: Vec<Vec2>,
+    //World space, zero for meshes without normals
+    pub normals: Vec<Vec3>,
+    //Which instance of the draw the vertex belongs to, reaches shading as FragmentInput::instance_id
+    pub instance_ids: Vec<u32>
+}
+
+impl VertexOutput {
+    //Divides by w, attributes are stored premultiplied by 1/w for perspective correct interpolation
+    pub fn push(&mut self, clip_position: Vec4, colour: Vec3, uv: Vec2, normal: Vec3, instance_id: u32) {
+        let inv_depth = 1.0 / clip_position.w;
+        self.ndc_positions.push((clip_position * inv_depth).truncate().extend(inv_depth));
+        self.colours.push(colour * inv_depth);
+        self.uvs.push(uv * inv_depth);
+        self.normals.push(normal * inv_depth);
+        self.instance_ids.push(instance_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IndexBuffer, Mesh, Topology, VertexInput};
+
+    #[test]
+    fn strips_and_fans_assemble_into_lists() {
+        let indices = IndexBuffer::from(vec![0u16, 1, 2, 3, u16::MAX, 4, 5, 6]);
+
+        //Without restart the restart index is just another vertex
+        assert_eq!(Topology::TriangleStrip.assemble(&indices, true), [0, 1, 2, 2, 1, 3, 4, 5, 6]);
+        assert_eq!(Topology::TriangleStrip.assemble(&indices, false).len(), 6 * 3);
+        assert_eq!(Topology::TriangleFan.assemble(&indices, true), [0, 1, 2, 0, 2, 3, 4, 5, 6]);
+        assert_eq!(Topology::LineStrip.assemble(&indices, true), [0, 1, 1, 2, 2, 3, 4, 5, 5, 6]);
+        assert_eq!(Topology::LineList.assemble(&indices, true), [0, 1, 2, 3, 4, 5]);
+        assert_eq!(Topology::TriangleList.assemble(&indices, true), [0, 1, 2, 4, 5, 6]);
+        assert_eq!(Topology::PointList.assemble(&indices, true), [0, 1, 2, 3, 4, 5, 6]);
+        //Lists without restart keep whole primitives only
+        assert_eq!(Topology::TriangleList.assemble(&indices, false), [0, 1, 2, 3, usize::from(u16::MAX), 4]);
+
+        //Meshes assemble once when they're built
+        let strip = Mesh::with_topology(VertexInput::default(), indices.clone(), Topology::TriangleStrip);
+        assert_eq!(strip.primitives().len(), 6 * 3);
+        assert_eq!(strip.with_primitive_restart().primitives(), [0, 1, 2, 2, 1, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn index_buffers_use_the_smallest_type() {
+        let small = IndexBuffer::from(vec![0usize, 1, 2, usize::MAX]);
+        assert_eq!(small, IndexBuffer::U16(vec![0, 1, 2, u16::MAX]));
+        assert_eq!(small.size_in_bytes(), 8);
+
+        let large = IndexBuffer::from(vec![0usize, 70000, usize::MAX]);
+        assert_eq!(large, IndexBuffer::U32(vec![0, 70000, u32::MAX]));
+        assert_eq!(large.restart_index(), u32::MAX as usize);
+        assert_eq!(large.iter().collect::<Vec<_>>(), [0, 70000, u32::MAX as usize]);
+    }
 }
@@ -1,95 +1,102 @@
+use glam::Vec3;
+use glam::Vec4;
+
 use crate::camera::Camera;
+use crate::texture::DepthTexture;
 use crate::texture::Texture;
-use crate::math::bounding_box::BoundingBox;
-use crate::math::bounding_box::Line;
-use crate::math::colour;
+use crate::math;
+use super::line;
+use super::state::BlendMode;
+use super::state::BlendState;
+use super::state::Viewport;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DebugLine {
+    pub start: Vec3,
+    pub end: Vec3,
+    //RGBA
+    pub colour: Vec4
+}
+
+impl DebugLine {
+    pub fn new(start: Vec3, end: Vec3, colour: Vec4) -> Self {
+        Self { start, end, colour }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LineMode {
+    #[default]
+    Aliased,
+    AntiAliased
+}
 
-#[derive(Default)]
 pub struct DebugLineShader {
-    pub camera: Camera
+    pub camera: Camera,
+    //In pixels
+    pub width: u32,
+    pub mode: LineMode
+}
+
+impl Default for DebugLineShader {
+    fn default() -> Self {
+        Self { camera: Camera::default(), width: 1, mode: LineMode::Aliased }
+    }
 }
 
 impl DebugLineShader {
-    pub fn dispatch(&self, out: &mut Texture, line_list: &[(glam::Vec3, glam::Vec3)]) {
+
+    //Lines are depth tested against depth_buffer if given, but never write to it
+    pub fn dispatch(&self, out: &mut Texture, depth_buffer: Option<&DepthTexture>, line_list: &[DebugLine]) {
 
         let (view, projection) = self.camera.generate_view_projection();
         let vp = projection * view;
 
-        let half_screen_width = (out.width() as f32) * 0.5;
-        let half_screen_height = (out.height() as f32) * 0.5;
-
-        let screen_space_matrix = glam::Mat3::from_scale_angle_translation(
-            glam::Vec2::new(half_screen_width, -half_screen_height),
-            0.0,
-            glam::Vec2::new(half_screen_width, half_screen_height)
-        );
+        let screen_space_matrix = Viewport::from_size(out.width(), out.height()).screen_space_matrix();
 
-        for (start, end) in line_list {
+        for line in line_list {
            
-            //calculate ndc coordinates of triangle
-            let proj_1 = vp * start.extend(1.0);
-            let proj_2 = vp * end.extend(1.0);
+            let proj_1 = vp * line.start.extend(1.0);
+            let proj_2 = vp * line.end.extend(1.0);
 
-            if proj_1.z > proj_1.w || proj_1.z < 0.0 || proj_2.z > proj_2.w || proj_2.z < 0.0 {
-                continue;
-            }
-
-            let inv_w1 = 1.0 / proj_1.w;
-            let inv_w2 = 1.0 / proj_2.w;
+            //Only near and far need clipping here, the rest is done in screen space
+            let Some((clip_1, clip_2, _, _)) = math::clip_homogenous_line(proj_1, proj_2, &[math::NEAR_PLANE, math::FAR_PLANE]) else { continue; };
 
             //Homogenous divide
-            let ndc_1 = proj_1.truncate() * inv_w1;
-            let ndc_2 = proj_2.truncate() * inv_w2;
+            let ndc_1 = clip_1.truncate() / clip_1.w;
+            let ndc_2 = clip_2.truncate() / clip_2.w;
 
-            self.draw_line(out, &screen_space_matrix, ndc_1, ndc_2);
-        }
-    }
+            let screen_1 = screen_space_matrix.mul_vec3(ndc_1.truncate().extend(1.0)).truncate().extend(ndc_1.z);
+            let screen_2 = screen_space_matrix.mul_vec3(ndc_2.truncate().extend(1.0)).truncate().extend(ndc_2.z);
 
-    fn draw_line(&self, out: &mut Texture, screen_space_matrix: &glam::Mat3, start: glam::Vec3, end: glam::Vec3) {
+            let (width, height) = (out.width(), out.height());
 
-        let screen_bounds = BoundingBox::new(
-            glam::UVec2::new(0, 0), 
-            glam::UVec2::new(out.width() as u32 - 1, out.height() as u32 - 1)
-        );
-
-        let screen_start = screen_space_matrix.mul_vec3(start).truncate();
-        let screen_end = screen_space_matrix.mul_vec3(end).truncate();
-
-        if let Some(clipped_line) = screen_bounds.clip_line(&Line::new(screen_start, screen_end)) {
-          
-            let clipped_start = clipped_line.start.as_uvec2();
-            let clipped_end = clipped_line.end.as_uvec2();
-
-            let mut x = clipped_start.x as i32;
-            let mut y = clipped_start.y as i32;
-
-            let final_x = clipped_end.x as i32;
-            let final_y = clipped_end.y as i32;
-
-            let dx = (final_x - x).abs();
-            let dy = -(final_y - y).abs();
-
-            let sx = if x < final_x { 1 } else { -1 }; 
-            let sy = if y < final_y { 1 } else { -1 }; 
-
-            let mut error = dy + dx;
-
-            loop {
-                out.write(x as usize, y as usize, colour::f32_to_hex(1.0, 0.0, 1.0, 0.0));
-
-                if x == final_x && y == final_y { break };
-                let e2 = 2 * error;
-
-                if e2 >= dy {
-                    error = error + dy;
-                    x = x + sx;
+            match self.mode {
+                LineMode::Aliased => {
+                    line::rasterize_line(width, height, screen_1, screen_2, self.width, |i, j, depth| {
+                        if depth_buffer.is_none_or(|buffer| depth <= buffer.read(i, j)) {
+                            DebugLineShader::blend(out, i, j, line.colour);
+                        }
+                    });
                 }
-
-                if e2 <= dx {
-                    error = error + dx;
-                    y = y + sy;
+                LineMode::AntiAliased => {
+                    line::rasterize_line_antialiased(width, height, screen_1, screen_2, self.width as f32, |i, j, depth, coverage| {
+                        if depth_buffer.is_none_or(|buffer| depth <= buffer.read(i, j)) {
+                            DebugLineShader::blend(out, i, j, line.colour * glam::Vec4::new(1.0, 1.0, 1.0, coverage));
+                        }
+                    });
                 }
             }
         }
     }
-}
\ No newline at end of file
+
+    //Alpha blends into the target in linear space, opaque colours are written directly
+    fn blend(out: &mut Texture, i: usize, j: usize, colour: Vec4) {
+        if colour.w >= 1.0 {
+            out.write_linear(i, j, colour);
+        } else {
+            let blended = BlendState { mode: BlendMode::Alpha }.blend(colour, out.read_linear(i, j));
+            out.write_linear(i, j, blended);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use glam::Mat4;
+use glam::Vec3;
+use glam::Vec4;
+
+use crate::camera::Camera;
+use crate::math::bounding_volume::Aabb;
+use crate::math::bounding_volume::Sphere;
+use crate::math::plane;
+use crate::texture::DepthTexture;
+use crate::texture::Texture;
+use super::debug::DebugLine;
+use super::debug::DebugLineShader;
+
+const CIRCLE_SEGMENTS: usize = 24;
+
+//Immediate mode recorder for debug primitives, flushed through the line shader.
+//Every primitive stays for the given number of flushes, at least one.
+#[derive(Default)]
+pub struct DebugDraw {
+    lines: Vec<(DebugLine, u32)>
+}
+
+impl DebugDraw {
+
+    pub fn line_count(&self) -> usize { self.lines.len() }
+
+    pub fn clear(&mut self) { self.lines.clear(); }
+
+    pub fn line(&mut self, start: Vec3, end: Vec3, colour: Vec4, frames: u32) {
+        self.lines.push((DebugLine::new(start, end, colour), frames.max(1)));
+    }
+
+    fn polyline(&mut self, points: &[Vec3], closed: bool, colour: Vec4, frames: u32) {
+        for pair in points.windows(2) {
+            self.line(pair[0], pair[1], colour, frames);
+        }
+
+        if closed && points.len() > 2 {
+            self.line(points[points.len() - 1], points[0], colour, frames);
+        }
+    }
+
+    fn box_corners(&mut self, corners: &[Vec3; 8], colour: Vec4, frames: u32) {
+        self.polyline(&corners[0..4], true, colour, frames);
+        self.polyline(&corners[4..8], true, colour, frames);
+
+        for i in 0..4 {
+            self.line(corners[i], corners[i + 4], colour, frames);
+        }
+    }
+
+    pub fn aabb(&mut self, aabb: &Aabb, colour: Vec4, frames: u32) {
+        self.box_corners(&aabb.corners(), colour, frames);
+    }
+
+    //Box given in local space, oriented by transform
+    pub fn obb(&mut self, local: &Aabb, transform: &Mat4, colour: Vec4, frames: u32) {
+        let corners = local.corners().map(|corner| transform.transform_point3(corner));
+        self.box_corners(&corners, colour, frames);
+    }
+
+    pub fn circle(&mut self, center: Vec3, normal: Vec3, radius: f32, colour: Vec4, frames: u32) {
+
+        let (tangent, bitangent) = normal.normalize().any_orthonormal_pair();
+
+        let points: Vec<Vec3> = (0..CIRCLE_SEGMENTS).map(|i| {
+            let angle = (i as f32) / (CIRCLE_SEGMENTS as f32) * std::f32::consts::TAU;
+            center + (tangent * angle.cos() + bitangent * angle.sin()) * radius
+        }).collect();
+
+        self.polyline(&points, true, colour, frames);
+    }
+
+    //Three great circles
+    pub fn sphere(&mut self, sphere: &Sphere, colour: Vec4, frames: u32) {
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            self.circle(sphere.center, axis, sphere.radius, colour, frames);
+        }
+    }
+
+    pub fn arrow(&mut self, start: Vec3, end: Vec3, colour: Vec4, frames: u32) {
+
+        self.line(start, end, colour, frames);
+
+        let shaft = end - start;
+        let length = shaft.length();
+        if length < f32::EPSILON { return; }
+
+        let direction = shaft / length;
+        let (tangent, bitangent) = direction.any_orthonormal_pair();
+        let head_length = length * 0.2;
+        let head_base = end - direction * head_length;
+
+        for side in [tangent, -tangent, bitangent, -bitangent] {
+            self.line(end, head_base + side * head_length * 0.5, colour, frames);
+        }
+    }
+
+    //Red, green and blue for x, y and z
+    pub fn axes(&mut self, transform: &Mat4, size: f32, frames: u32) {
+
+        let origin = transform.transform_point3(Vec3::ZERO);
+        let axes = [(Vec3::X, Vec4::new(1.0, 0.0, 0.0, 1.0)), (Vec3::Y, Vec4::new(0.0, 1.0, 0.0, 1.0)), (Vec3::Z, Vec4::new(0.0, 0.0, 1.0, 1.0))];
+
+        for (axis, colour) in axes {
+            self.arrow(origin, transform.transform_point3(axis * size), colour, frames);
+        }
+    }
+
+    //Grid on the XZ plane with cells in each direction from the center
+    pub fn grid(&mut self, center: Vec3, cell_size: f32, cells: u32, colour: Vec4, frames: u32) {
+
+        let extent = cell_size * cells as f32;
+
+        for i in -(cells as i32)..=(cells as i32) {
+            let offset = i as f32 * cell_size;
+
+            self.line(center + Vec3::new(offset, 0.0, -extent), center + Vec3::new(offset, 0.0, extent), colour, frames);
+            self.line(center + Vec3::new(-extent, 0.0, offset), center + Vec3::new(extent, 0.0, offset), colour, frames);
+        }
+    }
+
+    //Corners are found by intersecting the world space frustum planes
+    pub fn frustum(&mut self, camera: &Camera, colour: Vec4, frames: u32) {
+
+        let planes = camera.generate_frustum_perspective();
+        let (near, far) = (&planes[0], &planes[1]);
+        let (side_a, side_b, top, bottom) = (&planes[2], &planes[3], &planes[4], &planes[5]);
+
+        let quad = |cap: &plane::Plane| -> Option<[Vec3; 4]> {
+            Some([
+                plane::intersect_planes(cap, side_a, top)?,
+                plane::intersect_planes(cap, side_b, top)?,
+                plane::intersect_planes(cap, side_b, bottom)?,
+                plane::intersect_planes(cap, side_a, bottom)?,
+            ])
+        };
+
+        let (Some(near_quad), Some(far_quad)) = (quad(near), quad(far)) else { return; };
+
+        let mut corners = [Vec3::ZERO; 8];
+        corners[0..4].copy_from_slice(&near_quad);
+        corners[4..8].copy_from_slice(&far_quad);
+        self.box_corners(&corners, colour, frames);
+    }
+
+    //Draws everything recorded and drops primitives that ran out of frames
+    pub fn flush(&mut self, shader: &DebugLineShader, out: &mut Texture, depth_buffer: Option<&DepthTexture>) {
+
+        let lines: Vec<DebugLine> = self.lines.iter().map(|(line, _)| *line).collect();
+        shader.dispatch(out, depth_buffer, &lines);
+
+        self.lines.retain_mut(|(_, frames)| {
+            *frames -= 1;
+            *frames > 0
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec3, Vec4};
+
+    use super::DebugDraw;
+    use crate::renderer::debug::DebugLineShader;
+    use crate::texture::Texture;
+
+    #[test]
+    fn primitives_expire() {
+        let mut debug_draw = DebugDraw::default();
+        debug_draw.line(Vec3::ZERO, Vec3::X, Vec4::ONE, 1);
+        debug_draw.line(Vec3::ZERO, Vec3::Y, Vec4::ONE, 2);
+
+        let shader = DebugLineShader::default();
+        let mut out = Texture::new(4, 4);
+
+        debug_draw.flush(&shader, &mut out, None);
+        assert_eq!(debug_draw.line_count(), 1);
+
+        debug_draw.flush(&shader, &mut out, None);
+        assert_eq!(debug_draw.line_count(), 0);
+    }
+}
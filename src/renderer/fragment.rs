@@ -1,45 +1,120 @@
+use std::rc::Rc;
+
+use crate::texture::RenderTarget;
 use crate::texture::Sampler;
 use crate::texture::Texture;
 use crate::texture::DepthTexture;
 use crate::math;
+use super::data::InstanceData;
 use super::data::VertexOutput;
+use super::hiz::HiZBuffer;
+use super::hiz::TILE_SIZE;
+use super::line;
+use super::state::BlendState;
+use super::state::DepthState;
+use super::state::FillMode;
+use super::state::PointShape;
+use super::state::PointState;
+use super::state::RasterState;
+use super::state::Viewport;
+
+//Everything known about a fragment when it's shaded
+#[derive(Debug, Clone, Copy)]
+pub struct FragmentInput {
+    //Interpolated vertex attributes
+    pub colour: glam::Vec3,
+    pub uv: glam::Vec2,
+    //World space and normalised, zero for meshes without normals
+    pub normal: glam::Vec3,
+    //The bound texture sampled at uv
+    pub texel: glam::Vec4,
+    pub depth: f32,
+    //Index into the draw's instances, instance is the data it points to
+    pub instance_id: u32,
+    pub instance: InstanceData
+}
 
+//Replaces the fixed function colour * texel * tint, e.g. to use InstanceData::custom
+pub type ShadingFn = fn(&FragmentInput) -> glam::Vec4;
 
-#[derive(Default)]
 pub struct FragmentShader {
-    pub mesh_texture: Texture,
-    pub mesh_sampler: Sampler
+    pub mesh_texture: Rc<Texture>,
+    pub mesh_sampler: Sampler,
+    pub raster: RasterState,
+    pub depth: DepthState,
+    pub blend: BlendState,
+    pub point: PointState,
+    pub shading: Option<ShadingFn>,
+    //None covers the whole target
+    pub viewport: Option<Viewport>
+}
+
+impl Default for FragmentShader {
+    fn default() -> Self {
+        Self {
+            mesh_texture: Rc::new(Texture::solid(0xFFFFFFFF)),
+            mesh_sampler: Sampler::default(),
+            raster: RasterState::default(),
+            depth: DepthState::default(),
+            blend: BlendState::default(),
+            point: PointState::default(),
+            shading: None,
+            viewport: None
+        }
+    }
 }
 
 impl FragmentShader {
-    pub fn dispatch(&self, out: &mut Texture, depth_buffer: &mut DepthTexture, vs_output: &VertexOutput, indices: &[usize]) {
+    pub fn dispatch<T: RenderTarget>(&self, out: &mut T, depth_buffer: &mut DepthTexture, vs_output: &VertexOutput, indices: &[usize]) {
+        self.dispatch_instanced(out, depth_buffer, None, vs_output, indices, &[]);
+    }
+
+    //Instance ids written by the vertex shader index into instances, missing ones use the default.
+    //hiz has to match depth_buffer, it's refreshed once everything is drawn
+    pub fn dispatch_instanced<T: RenderTarget>(&self, out: &mut T, depth_buffer: &mut DepthTexture, mut hiz: Option<&mut HiZBuffer>, vs_output: &VertexOutput, indices: &[usize], instances: &[InstanceData]) {
 
         debug_assert!(out.width() == depth_buffer.width());
         debug_assert!(out.height() == depth_buffer.height());
 
-        let half_screen_width = (out.width() as f32) * 0.5;
-        let half_screen_height = (out.height() as f32) * 0.5;
-
-        let screen_space_matrix = glam::Mat3::from_scale_angle_translation(
-            glam::Vec2::new(half_screen_width, -half_screen_height),
-            0.0,
-            glam::Vec2::new(half_screen_width, half_screen_height)
-        );
+        let viewport = self.viewport.unwrap_or(Viewport::from_size(out.width(), out.height()));
+        let screen_space_matrix = viewport.screen_space_matrix();
+        let screen_bounds = viewport.bounds(out.width(), out.height());
 
         let triangle_count = indices.len() / 3;
 
         for i in 0..triangle_count {
             let triangle_indices = [indices[i * 3], indices[i * 3 + 1], indices[i * 3 + 2]];
-            self.rasterize_triangle(out, depth_buffer, vs_output, triangle_indices, &screen_space_matrix);
+            let instance_id = vs_output.instance_ids[triangle_indices[0]];
+            self.rasterize_triangle(out, depth_buffer, hiz.as_deref_mut(), vs_output, triangle_indices, (instance_id, &Self::instance(instances, instance_id)), &screen_space_matrix, &screen_bounds);
         }
+
+        if let Some(hiz) = hiz { hiz.refresh(depth_buffer); }
     }
 
-    fn rasterize_triangle(&self, out: &mut Texture, depth_buffer: &mut DepthTexture, vs_output: &VertexOutput, indices: [usize; 3], screen_matrix: &glam::Mat3) -> Option<()> {
+    fn instance(instances: &[InstanceData], instance_id: u32) -> InstanceData {
+        instances.get(instance_id as usize).copied().unwrap_or_default()
+    }
+
+    //Fixed function colour * texel * tint unless a shading function is set
+    fn shade(&self, colour: glam::Vec3, uv: glam::Vec2, normal: glam::Vec3, depth: f32, (instance_id, instance): (u32, &InstanceData)) -> glam::Vec4 {
+        let texel = self.mesh_sampler.sample(self.mesh_texture.as_ref(), uv);
+        match self.shading {
+            Some(shading) => shading(&FragmentInput { colour, uv, normal: normal.normalize_or_zero(), texel, depth, instance_id, instance: *instance }),
+            None => colour.extend(1.0) * texel * instance.tint
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn rasterize_triangle<T: RenderTarget>(&self, out: &mut T, depth_buffer: &mut DepthTexture, mut hiz: Option<&mut HiZBuffer>, vs_output: &VertexOutput, indices: [usize; 3], instance: (u32, &InstanceData), screen_matrix: &glam::Mat3, screen_bounds: &math::bounding_box::BoundingBox) -> Option<()> {
         
         let v1 = vs_output.ndc_positions[indices[0]];
         let v2 = vs_output.ndc_positions[indices[1]];
         let v3 = vs_output.ndc_positions[indices[2]];
 
+        //Winding in NDC, positive is counter clockwise
+        let ndc_area = math::edge_function(v3.truncate().truncate(), v1.truncate().truncate(), v2.truncate().truncate());
+        if self.raster.should_cull(ndc_area) { return None; }
+
         let colour1 = vs_output.colours[indices[0]];
         let colour2 = vs_output.colours[indices[1]];
         let colour3 = vs_output.colours[indices[2]];
@@ -48,40 +123,92 @@ impl FragmentShader {
         let uv2 = vs_output.uvs[indices[1]];
         let uv3 = vs_output.uvs[indices[2]];
 
+        let normal1 = vs_output.normals[indices[0]];
+        let normal2 = vs_output.normals[indices[1]];
+        let normal3 = vs_output.normals[indices[2]];
+
         let screen_1 = screen_matrix.mul_vec3(v1.truncate().truncate().extend(1.0));
         let screen_2 = screen_matrix.mul_vec3(v2.truncate().truncate().extend(1.0));
         let screen_3 = screen_matrix.mul_vec3(v3.truncate().truncate().extend(1.0));
 
         let triangle_bounds = math::generate_triangle_bounding_box(screen_1.truncate(), screen_2.truncate(), screen_3.truncate());
+        let triangle_bounds = triangle_bounds.intersect(screen_bounds)?;
 
-        let screen_bounds = math::bounding_box::BoundingBox::new(
-            glam::UVec2::new(0, 0), 
-            glam::UVec2::new(out.width() as u32, out.height() as u32)
-        );
-
-        let triangle_bounds = triangle_bounds.intersect(&screen_bounds)?;
+        //Pixel distance to an edge is its weight times the triangle height over that edge
+        let double_area = math::edge_function(screen_3.truncate(), screen_1.truncate(), screen_2.truncate()).abs();
+        let inverse_heights = glam::Vec3::new(
+            (screen_3 - screen_2).truncate().length(),
+            (screen_1 - screen_3).truncate().length(),
+            (screen_2 - screen_1).truncate().length()
+        ) / double_area.max(f32::EPSILON);
 
         let x_range = (triangle_bounds.start.x as usize)..(triangle_bounds.end.x as usize);
         let y_range = (triangle_bounds.start.y as usize)..(triangle_bounds.end.y as usize);
 
-        for j in y_range {
-            for i in x_range.clone() {
+        //The whole triangle, then tile by tile, against the depth pyramid
+        let depth_range = (v1.z.min(v2.z).min(v3.z), v1.z.max(v2.z).max(v3.z));
+        if let Some(hiz) = hiz.as_deref() {
+            if self.depth.fails_range(depth_range, hiz.rect_range(&triangle_bounds)) { return None; }
+        }
+
+        for tile_y in (y_range.start / TILE_SIZE)..y_range.end.div_ceil(TILE_SIZE) {
+            for tile_x in (x_range.start / TILE_SIZE)..x_range.end.div_ceil(TILE_SIZE) {
+
+                let mut passes_tile = false;
+                if let Some(hiz) = hiz.as_deref() {
+                    let tile_range = hiz.tile_range(tile_x, tile_y);
+                    if self.depth.fails_range(depth_range, tile_range) { continue; }
+                    passes_tile = self.depth.passes_range(depth_range, tile_range);
+                }
+
+                let tile_y_range = (tile_y * TILE_SIZE).max(y_range.start)..((tile_y + 1) * TILE_SIZE).min(y_range.end);
+                let tile_x_range = (tile_x * TILE_SIZE).max(x_range.start)..((tile_x + 1) * TILE_SIZE).min(x_range.end);
+
+                for j in tile_y_range {
+                    for i in tile_x_range.clone() {
                 
-                let pixel_point = glam::Vec2::new(i as f32 + 0.5, j as f32 + 0.5);
+                        let pixel_point = glam::Vec2::new(i as f32 + 0.5, j as f32 + 0.5);
+
+                        //means the point is inside the triangle
+                        if let Some(weights) = math::barycentric_weights(pixel_point, screen_1.truncate(), screen_2.truncate(), screen_3.truncate()) {
 
-                //means the point is inside the triangle
-                if let Some(weights) = math::barycentric_weights(pixel_point, screen_1.truncate(), screen_2.truncate(), screen_3.truncate()) {
-                    let depth = weights.dot(glam::Vec3::new(v1.z, v2.z, v3.z));
+                            //How much of the pixel the wireframe covers
+                            let edge_coverage = match self.raster.fill_mode {
+                                FillMode::Solid => 0.0,
+                                _ => {
+                                    let edge_distance = (weights / inverse_heights).min_element();
+                                    (self.raster.wireframe_width * 0.5 + 0.5 - edge_distance).clamp(0.0, 1.0)
+                                }
+                            };
 
-                    if depth_buffer.depth_test(i, j, depth) {
+                            if self.raster.fill_mode == FillMode::Wireframe && edge_coverage <= 0.0 { continue; }
+
+                            let depth = weights.dot(glam::Vec3::new(v1.z, v2.z, v3.z));
+
+                            if passes_tile || self.depth.passes(depth, depth_buffer.read(i, j)) {
                         
-                        let depth_correction = 1.0 / (weights.x * v1.w + weights.y * v2.w + weights.z * v3.w);
-                        let colour = math::barycentric_lerp(weights, colour1, colour2, colour3) * depth_correction;
-                        let uv = math::barycentric_lerp(weights, uv1, uv2, uv3) * depth_correction;
+                                let depth_correction = 1.0 / (weights.x * v1.w + weights.y * v2.w + weights.z * v3.w);
+                                let colour = math::barycentric_lerp(weights, colour1, colour2, colour3) * depth_correction;
+                                let uv = math::barycentric_lerp(weights, uv1, uv2, uv3) * depth_correction;
+                                let normal = math::barycentric_lerp(weights, normal1, normal2, normal3) * depth_correction;
+
+                                let mut out_frag = self.shade(colour, uv, normal, depth, instance);
 
-                        let out_frag = colour * self.mesh_sampler.sample(&self.mesh_texture, uv).truncate();
+                                match self.raster.fill_mode {
+                                    FillMode::Solid => (),
+                                    FillMode::Wireframe => {
+                                        let background = out.read_colour(i, j);
+                                        out_frag = math::lerp(background, self.raster.wireframe_colour.extend(1.0), edge_coverage);
+                                    }
+                                    FillMode::SolidWireframe => {
+                                        let wire = self.raster.wireframe_colour.extend(out_frag.w);
+                                        out_frag = math::lerp(out_frag, wire, edge_coverage);
+                                    }
+                                }
 
-                        out.write(i, j, math::colour::vec4_to_hex(out_frag.extend(1.0)));
+                                self.output_merge(out, depth_buffer, hiz.as_deref_mut(), i, j, depth, out_frag);
+                            }
+                        }
                     }
                 }
             }
@@ -89,4 +216,132 @@ impl FragmentShader {
 
         Some(())
     }
-}
\ No newline at end of file
+
+    //Writes a fragment that already passed the depth test
+    #[allow(clippy::too_many_arguments)]
+    fn output_merge<T: RenderTarget>(&self, out: &mut T, depth_buffer: &mut DepthTexture, hiz: Option<&mut HiZBuffer>, i: usize, j: usize, depth: f32, fragment: glam::Vec4) {
+
+        if self.depth.write {
+            depth_buffer.write(i, j, depth);
+            if let Some(hiz) = hiz { hiz.record_write(i, j, depth); }
+        }
+
+        if self.blend.is_opaque() {
+            out.write_colour(i, j, fragment.truncate().extend(1.0));
+        } else {
+            let blended = self.blend.blend(fragment, out.read_colour(i, j));
+            out.write_colour(i, j, blended);
+        }
+    }
+
+    pub fn dispatch_lines<T: RenderTarget>(&self, out: &mut T, depth_buffer: &mut DepthTexture, vs_output: &VertexOutput, indices: &[usize]) {
+        self.dispatch_lines_instanced(out, depth_buffer, None, vs_output, indices, &[]);
+    }
+
+    //Every pair of indices is a line of raster.line_width pixels
+    pub fn dispatch_lines_instanced<T: RenderTarget>(&self, out: &mut T, depth_buffer: &mut DepthTexture, mut hiz: Option<&mut HiZBuffer>, vs_output: &VertexOutput, indices: &[usize], instances: &[InstanceData]) {
+
+        debug_assert!(out.width() == depth_buffer.width());
+        debug_assert!(out.height() == depth_buffer.height());
+
+        let viewport = self.viewport.unwrap_or(Viewport::from_size(out.width(), out.height()));
+        let screen_space_matrix = viewport.screen_space_matrix();
+        let screen_bounds = viewport.bounds(out.width(), out.height());
+
+        for line in indices.chunks_exact(2) {
+            let instance_id = vs_output.instance_ids[line[0]];
+            self.rasterize_line(out, depth_buffer, hiz.as_deref_mut(), vs_output, [line[0], line[1]], (instance_id, &Self::instance(instances, instance_id)), &screen_space_matrix, &screen_bounds);
+        }
+
+        if let Some(hiz) = hiz { hiz.refresh(depth_buffer); }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn rasterize_line<T: RenderTarget>(&self, out: &mut T, depth_buffer: &mut DepthTexture, mut hiz: Option<&mut HiZBuffer>, vs_output: &VertexOutput, indices: [usize; 2], instance: (u32, &InstanceData), screen_matrix: &glam::Mat3, screen_bounds: &math::bounding_box::BoundingBox) {
+
+        let ends = indices.map(|index| vs_output.ndc_positions[index]);
+        let screen = ends.map(|v| screen_matrix.mul_vec3(v.truncate().truncate().extend(1.0)).truncate());
+
+        let direction = screen[1] - screen[0];
+        let length_squared = direction.length_squared().max(f32::EPSILON);
+        let (width, height) = (out.width(), out.height());
+
+        line::rasterize_line(width, height, screen[0].extend(ends[0].z), screen[1].extend(ends[1].z), self.raster.line_width, |i, j, depth| {
+
+            if (i as u32) < screen_bounds.start.x || (j as u32) < screen_bounds.start.y || (i as u32) >= screen_bounds.end.x || (j as u32) >= screen_bounds.end.y { return; }
+            if !self.depth.passes(depth, depth_buffer.read(i, j)) { return; }
+
+            //Position along the line in screen space, attributes are interpolated over 1/w
+            let t = ((glam::Vec2::new(i as f32 + 0.5, j as f32 + 0.5) - screen[0]).dot(direction) / length_squared).clamp(0.0, 1.0);
+            let depth_correction = 1.0 / math::lerp(ends[0].w, ends[1].w, t);
+            let colour = math::lerp(vs_output.colours[indices[0]], vs_output.colours[indices[1]], t) * depth_correction;
+            let uv = math::lerp(vs_output.uvs[indices[0]], vs_output.uvs[indices[1]], t) * depth_correction;
+            let normal = math::lerp(vs_output.normals[indices[0]], vs_output.normals[indices[1]], t) * depth_correction;
+
+            let out_frag = self.shade(colour, uv, normal, depth, instance);
+            self.output_merge(out, depth_buffer, hiz.as_deref_mut(), i, j, depth, out_frag);
+        });
+    }
+
+    pub fn dispatch_points<T: RenderTarget>(&self, out: &mut T, depth_buffer: &mut DepthTexture, vs_output: &VertexOutput, indices: &[usize]) {
+        self.dispatch_points_instanced(out, depth_buffer, None, vs_output, indices, &[]);
+    }
+
+    //Every index is one point, drawn as a screen aligned square of point.size pixels
+    pub fn dispatch_points_instanced<T: RenderTarget>(&self, out: &mut T, depth_buffer: &mut DepthTexture, mut hiz: Option<&mut HiZBuffer>, vs_output: &VertexOutput, indices: &[usize], instances: &[InstanceData]) {
+
+        debug_assert!(out.width() == depth_buffer.width());
+        debug_assert!(out.height() == depth_buffer.height());
+
+        let viewport = self.viewport.unwrap_or(Viewport::from_size(out.width(), out.height()));
+        let screen_space_matrix = viewport.screen_space_matrix();
+        let screen_bounds = viewport.bounds(out.width(), out.height());
+
+        for index in indices {
+            let instance_id = vs_output.instance_ids[*index];
+            self.rasterize_point(out, depth_buffer, hiz.as_deref_mut(), vs_output, *index, (instance_id, &Self::instance(instances, instance_id)), &screen_space_matrix, &screen_bounds);
+        }
+
+        if let Some(hiz) = hiz { hiz.refresh(depth_buffer); }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn rasterize_point<T: RenderTarget>(&self, out: &mut T, depth_buffer: &mut DepthTexture, mut hiz: Option<&mut HiZBuffer>, vs_output: &VertexOutput, index: usize, instance: (u32, &InstanceData), screen_matrix: &glam::Mat3, screen_bounds: &math::bounding_box::BoundingBox) -> Option<()> {
+
+        let v = vs_output.ndc_positions[index];
+        let depth_correction = 1.0 / v.w;
+        let colour = vs_output.colours[index] * depth_correction;
+        let vertex_uv = vs_output.uvs[index] * depth_correction;
+        let normal = vs_output.normals[index] * depth_correction;
+
+        let center = screen_matrix.mul_vec3(v.truncate().truncate().extend(1.0)).truncate();
+        let half_size = (self.point.size * 0.5).max(0.5);
+
+        let point_bounds = math::bounding_box::BoundingBox::new(
+            (center - half_size).round().max(glam::Vec2::ZERO).as_uvec2(),
+            (center + half_size).round().max(glam::Vec2::ZERO).as_uvec2()
+        ).intersect(screen_bounds)?;
+
+        if let Some(hiz) = hiz.as_deref() {
+            if self.depth.fails_range((v.z, v.z), hiz.rect_range(&point_bounds)) { return None; }
+        }
+
+        for j in (point_bounds.start.y as usize)..(point_bounds.end.y as usize) {
+            for i in (point_bounds.start.x as usize)..(point_bounds.end.x as usize) {
+
+                //Position inside the point, -1 to 1 with y up
+                let offset = (glam::Vec2::new(i as f32 + 0.5, j as f32 + 0.5) - center) / half_size * glam::Vec2::new(1.0, -1.0);
+                if self.point.shape == PointShape::Round && offset.length_squared() > 1.0 { continue; }
+
+                if !self.depth.passes(v.z, depth_buffer.read(i, j)) { continue; }
+
+                let uv = if self.point.sprite { offset * 0.5 + 0.5 } else { vertex_uv };
+                let out_frag = self.shade(colour, uv, normal, v.z, instance);
+
+                self.output_merge(out, depth_buffer, hiz.as_deref_mut(), i, j, v.z, out_frag);
+            }
+        }
+
+        Some(())
+    }
+}
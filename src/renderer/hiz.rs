@@ -0,0 +1,277 @@
+use glam::Mat3;
+use glam::Mat4;
+use glam::Vec2;
+
+use crate::math::bounding_box::BoundingBox;
+use crate::math::bounding_volume::Aabb;
+use crate::texture::DepthTexture;
+use super::state::DepthState;
+
+//Pixels per side of a tile on the finest level
+pub const TILE_SIZE: usize = 8;
+
+struct HiZLevel {
+    width: usize,
+    height: usize,
+    //(min, max) depth per cell
+    ranges: Vec<(f32, f32)>
+}
+
+//Min and max depth per tile of a DepthTexture, each coarser level covers 2x2 cells of the one
+//below until one cell covers the whole target. Ranges only ever grow when depth is written,
+//so they always contain the stored depths. refresh shrinks the tiles that changed back down.
+pub struct HiZBuffer {
+    levels: Vec<HiZLevel>,
+    dirty: Vec<usize>,
+    is_dirty: Vec<bool>
+}
+
+impl HiZBuffer {
+    //Matches a new DepthTexture, every depth is 1
+    pub fn new(width: usize, height: usize) -> Self {
+
+        let mut levels = Vec::new();
+        let (mut level_width, mut level_height) = (width.div_ceil(TILE_SIZE).max(1), height.div_ceil(TILE_SIZE).max(1));
+
+        loop {
+            levels.push(HiZLevel { width: level_width, height: level_height, ranges: vec![(1.0, 1.0); level_width * level_height] });
+            if level_width == 1 && level_height == 1 { break; }
+            (level_width, level_height) = (level_width.div_ceil(2), level_height.div_ceil(2));
+        }
+
+        let tiles = levels[0].ranges.len();
+        Self { levels, dirty: Vec::new(), is_dirty: vec![false; tiles] }
+    }
+
+    pub fn from_depth(depth: &DepthTexture) -> Self {
+        let mut hiz = Self::new(depth.width(), depth.height());
+        hiz.dirty = (0..hiz.levels[0].ranges.len()).collect();
+        hiz.is_dirty.fill(true);
+        hiz.refresh(depth);
+        hiz
+    }
+
+    pub fn level_count(&self) -> usize { self.levels.len() }
+    pub fn tiles(&self) -> (usize, usize) { (self.levels[0].width, self.levels[0].height) }
+
+    pub fn range(&self, level: usize, x: usize, y: usize) -> (f32, f32) {
+        let level = &self.levels[level];
+        level.ranges[y * level.width + x]
+    }
+
+    pub fn tile_range(&self, x: usize, y: usize) -> (f32, f32) { self.range(0, x, y) }
+
+    //Call alongside DepthTexture::clear
+    pub fn clear(&mut self, depth: f32) {
+        for level in &mut self.levels {
+            level.ranges.fill((depth, depth));
+        }
+        self.dirty.clear();
+        self.is_dirty.fill(false);
+    }
+
+    //Call for every depth written to the DepthTexture
+    pub fn record_write(&mut self, i: usize, j: usize, depth: f32) {
+
+        let (tile_x, tile_y) = (i / TILE_SIZE, j / TILE_SIZE);
+        let tile = tile_y * self.levels[0].width + tile_x;
+
+        if !self.is_dirty[tile] {
+            self.is_dirty[tile] = true;
+            self.dirty.push(tile);
+        }
+
+        //Parents contain their children, so once one already covers depth all above it do too
+        for (l, level) in self.levels.iter_mut().enumerate() {
+            let range = &mut level.ranges[(tile_y >> l) * level.width + (tile_x >> l)];
+            if depth >= range.0 && depth <= range.1 { break; }
+            *range = (range.0.min(depth), range.1.max(depth));
+        }
+    }
+
+    //Tightens the tiles written since the last refresh to the depths actually stored
+    pub fn refresh(&mut self, depth: &DepthTexture) {
+
+        if self.dirty.is_empty() { return; }
+
+        let mut cells = std::mem::take(&mut self.dirty);
+        let base_width = self.levels[0].width;
+
+        for tile in &cells {
+            let (tile_x, tile_y) = (tile % base_width, tile / base_width);
+            let mut range = (f32::INFINITY, f32::NEG_INFINITY);
+
+            for j in (tile_y * TILE_SIZE)..((tile_y + 1) * TILE_SIZE).min(depth.height()) {
+                for i in (tile_x * TILE_SIZE)..((tile_x + 1) * TILE_SIZE).min(depth.width()) {
+                    let value = depth.read(i, j);
+                    range = (range.0.min(value), range.1.max(value));
+                }
+            }
+
+            self.levels[0].ranges[*tile] = range;
+            self.is_dirty[*tile] = false;
+        }
+
+        for l in 1..self.levels.len() {
+
+            let child_width = self.levels[l - 1].width;
+            let child_height = self.levels[l - 1].height;
+            let parent_width = self.levels[l].width;
+
+            cells = cells.iter().map(|cell| (cell / child_width / 2) * parent_width + (cell % child_width) / 2).collect();
+            cells.sort_unstable();
+            cells.dedup();
+
+            let (children, parents) = self.levels.split_at_mut(l);
+            let (children, parent) = (&children[l - 1], &mut parents[0]);
+
+            for cell in &cells {
+                let (x, y) = (cell % parent_width, cell / parent_width);
+                let mut range = (f32::INFINITY, f32::NEG_INFINITY);
+
+                for child_y in (y * 2)..(y * 2 + 2).min(child_height) {
+                    for child_x in (x * 2)..(x * 2 + 2).min(child_width) {
+                        let child = children.ranges[child_y * child_width + child_x];
+                        range = (range.0.min(child.0), range.1.max(child.1));
+                    }
+                }
+
+                parent.ranges[*cell] = range;
+            }
+        }
+    }
+
+    //Depth range over the pixels in bounds, from the finest level where it spans at most 2x2 cells.
+    //Empty bounds cover nothing and give the empty range (INFINITY, NEG_INFINITY)
+    pub fn rect_range(&self, bounds: &BoundingBox) -> (f32, f32) {
+
+        if bounds.start.x >= bounds.end.x || bounds.start.y >= bounds.end.y { return (f32::INFINITY, f32::NEG_INFINITY); }
+
+        let start = (bounds.start.x as usize / TILE_SIZE, bounds.start.y as usize / TILE_SIZE);
+        let end = ((bounds.end.x as usize).saturating_sub(1) / TILE_SIZE, (bounds.end.y as usize).saturating_sub(1) / TILE_SIZE);
+
+        let level = (0..self.levels.len())
+            .find(|l| (end.0 >> l) - (start.0 >> l) <= 1 && (end.1 >> l) - (start.1 >> l) <= 1)
+            .unwrap_or(self.levels.len() - 1);
+
+        let cells = &self.levels[level];
+        let mut range = (f32::INFINITY, f32::NEG_INFINITY);
+
+        for y in (start.1 >> level)..=(end.1 >> level).min(cells.height - 1) {
+            for x in (start.0 >> level)..=(end.0 >> level).min(cells.width - 1) {
+                let cell = cells.ranges[y * cells.width + x];
+                range = (range.0.min(cell.0), range.1.max(cell.1));
+            }
+        }
+
+        range
+    }
+
+    //True when the box can't pass the depth test anywhere it covers on screen.
+    //Boxes crossing the near plane are never occluded
+    pub fn is_occluded(&self, aabb: &Aabb, mvp: &Mat4, screen_matrix: &Mat3, screen_bounds: &BoundingBox, depth_state: &DepthState) -> bool {
+
+        let mut ndc_min = glam::Vec3::splat(f32::INFINITY);
+        let mut ndc_max = glam::Vec3::splat(f32::NEG_INFINITY);
+
+        for corner in aabb.corners() {
+            let clip = *mvp * corner.extend(1.0);
+            if clip.z < 0.0 || clip.w <= 0.0 { return false; }
+
+            let ndc = clip.truncate() / clip.w;
+            ndc_min = ndc_min.min(ndc);
+            ndc_max = ndc_max.max(ndc);
+        }
+
+        //NDC y is up and screen y is down, so the corners swap
+        let screen_a = screen_matrix.mul_vec3(Vec2::new(ndc_min.x, ndc_max.y).extend(1.0)).truncate();
+        let screen_b = screen_matrix.mul_vec3(Vec2::new(ndc_max.x, ndc_min.y).extend(1.0)).truncate();
+
+        let rect = BoundingBox::new(
+            screen_a.min(screen_b).floor().max(Vec2::ZERO).as_uvec2(),
+            screen_a.max(screen_b).ceil().max(Vec2::ZERO).as_uvec2()
+        );
+
+        let Some(rect) = rect.intersect(screen_bounds) else { return false; };
+        if rect.start.x >= rect.end.x || rect.start.y >= rect.end.y { return false; }
+
+        depth_state.fails_range((ndc_min.z, ndc_max.z.min(1.0)), self.rect_range(&rect))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, UVec2, Vec3};
+
+    use super::{HiZBuffer, TILE_SIZE};
+    use crate::math::bounding_box::BoundingBox;
+    use crate::math::bounding_volume::Aabb;
+    use crate::renderer::state::{DepthState, Viewport};
+    use crate::texture::DepthTexture;
+
+    #[test]
+    fn ranges_grow_on_write_and_shrink_on_refresh() {
+        let mut depth = DepthTexture::new(20, 12);
+        let mut hiz = HiZBuffer::new(20, 12);
+        assert_eq!(hiz.tiles(), (3, 2));
+        assert_eq!(hiz.level_count(), 3);
+
+        depth.write(9, 1, 0.25);
+        hiz.record_write(9, 1, 0.25);
+        assert_eq!(hiz.tile_range(1, 0), (0.25, 1.0));
+        assert_eq!(hiz.range(2, 0, 0), (0.25, 1.0));
+
+        //Overwritten before the refresh, the range is still the conservative one until then
+        depth.write(9, 1, 0.5);
+        hiz.record_write(9, 1, 0.5);
+        assert_eq!(hiz.tile_range(1, 0), (0.25, 1.0));
+
+        hiz.refresh(&depth);
+        assert_eq!(hiz.tile_range(1, 0), (0.5, 1.0));
+        assert_eq!(hiz.range(2, 0, 0), (0.5, 1.0));
+
+        //Every pixel of the tile covered brings its max down
+        depth.clear(0.1);
+        hiz.clear(0.1);
+        assert_eq!(hiz.rect_range(&BoundingBox::new(UVec2::ZERO, UVec2::new(20, 12))), (0.1, 0.1));
+        assert_eq!(HiZBuffer::from_depth(&depth).tile_range(2, 1), (0.1, 0.1));
+        assert_eq!(TILE_SIZE, 8);
+    }
+
+    #[test]
+    fn empty_rects_cover_nothing() {
+        let mut hiz = HiZBuffer::new(20, 12);
+        hiz.clear(0.5);
+
+        //Zero width on a tile boundary used to underflow
+        assert_eq!(hiz.rect_range(&BoundingBox::new(UVec2::new(8, 4), UVec2::new(8, 12))), (f32::INFINITY, f32::NEG_INFINITY));
+        assert_eq!(hiz.rect_range(&BoundingBox::new(UVec2::new(0, 0), UVec2::new(20, 0))), (f32::INFINITY, f32::NEG_INFINITY));
+    }
+
+    #[test]
+    fn boxes_behind_stored_depth_are_occluded() {
+        let viewport = Viewport::from_size(32, 32);
+        let (screen_matrix, screen_bounds) = (viewport.screen_space_matrix(), viewport.bounds(32, 32));
+
+        let mut depth = DepthTexture::new(32, 32);
+        depth.clear(0.5);
+        let hiz = HiZBuffer::from_depth(&depth);
+
+        let projection = Mat4::perspective_rh(1.0, 1.0, 0.1, 10.0);
+        let unit = Aabb::new(Vec3::splat(-0.5), Vec3::splat(0.5));
+        let near = Mat4::from_translation(Vec3::new(0.0, 0.0, -1.0));
+        let far = Mat4::from_translation(Vec3::new(0.0, 0.0, -8.0));
+
+        //Stored depth 0.5 is about 0.2 units in front of the camera
+        assert!(hiz.is_occluded(&unit, &(projection * near), &screen_matrix, &screen_bounds, &DepthState::default()));
+        assert!(hiz.is_occluded(&unit, &(projection * far), &screen_matrix, &screen_bounds, &DepthState::default()));
+
+        let mut cleared = HiZBuffer::new(32, 32);
+        cleared.clear(1.0);
+        assert!(!cleared.is_occluded(&unit, &(projection * far), &screen_matrix, &screen_bounds, &DepthState::default()));
+
+        //Crossing the near plane
+        let around_camera = Mat4::IDENTITY;
+        assert!(!hiz.is_occluded(&unit, &(projection * around_camera), &screen_matrix, &screen_bounds, &DepthState::default()));
+    }
+}
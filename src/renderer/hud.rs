@@ -0,0 +1,61 @@
+use std::collections::VecDeque;
+
+use crate::texture::Texture;
+use super::command::DrawStats;
+use super::culling::CullingStats;
+
+const FRAME_HISTORY: usize = 60;
+const MARGIN: usize = 4;
+
+//On screen statistics overlay, frame times are averaged over the last frames
+pub struct Hud {
+    pub enabled: bool,
+    pub colour: u32,
+    pub shadow_colour: u32,
+    frame_times: VecDeque<f32>
+}
+
+impl Default for Hud {
+    fn default() -> Self {
+        Self { enabled: true, colour: 0xFFFFFFFF, shadow_colour: 0xFF000000, frame_times: VecDeque::with_capacity(FRAME_HISTORY) }
+    }
+}
+
+impl Hud {
+
+    pub fn update(&mut self, dt: f32) {
+        if self.frame_times.len() == FRAME_HISTORY { self.frame_times.pop_front(); }
+        self.frame_times.push_back(dt);
+    }
+
+    //Average over the history, in seconds
+    pub fn frame_time(&self) -> f32 {
+        if self.frame_times.is_empty() { return 0.0; }
+        self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32
+    }
+
+    pub fn text(&self, width: usize, height: usize, draw_stats: &DrawStats, culling_stats: &CullingStats) -> String {
+        let frame_time = self.frame_time();
+        let fps = if frame_time > 0.0 { 1.0 / frame_time } else { 0.0 };
+
+        format!(
+            "FPS {:.1}\nFrame {:.2} ms\nPrimitives {} / {}\nDraws {}  Culled {} / {}  Occluded {}\nVertex cache {:.0}%\nResolution {}x{}",
+            fps,
+            frame_time * 1000.0,
+            draw_stats.rasterized_primitives, draw_stats.input_primitives,
+            draw_stats.draw_calls, culling_stats.culled, culling_stats.tested, draw_stats.occluded_instances,
+            draw_stats.vertex_cache.hit_rate() * 100.0,
+            width, height
+        )
+    }
+
+    pub fn draw(&self, out: &mut Texture, draw_stats: &DrawStats, culling_stats: &CullingStats) {
+        if !self.enabled { return; }
+
+        let text = self.text(out.width(), out.height(), draw_stats, culling_stats);
+
+        //Drop shadow keeps it readable on bright backgrounds
+        out.draw_text(MARGIN + 1, MARGIN + 1, &text, self.shadow_colour);
+        out.draw_text(MARGIN, MARGIN, &text, self.colour);
+    }
+}
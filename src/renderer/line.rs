@@ -0,0 +1,172 @@
+use glam::Vec2;
+use glam::Vec3;
+
+use crate::math::bounding_box::BoundingBox;
+use crate::math::bounding_box::Line;
+
+//Screen space line rasterization shared by the line drawing shaders.
+//Points are in pixels with the NDC depth in z, plot receives (i, j, depth).
+pub fn rasterize_line(target_width: usize, target_height: usize, start: Vec3, end: Vec3, width: u32, mut plot: impl FnMut(usize, usize, f32)) {
+
+    //Nothing to draw into, and the bounds below would underflow
+    if target_width == 0 || target_height == 0 { return; }
+
+    let screen_bounds = BoundingBox::new(
+        glam::UVec2::new(0, 0),
+        glam::UVec2::new(target_width as u32 - 1, target_height as u32 - 1)
+    );
+
+    let Some(clipped_line) = screen_bounds.clip_line(&Line::new(start.truncate(), end.truncate())) else { return; };
+
+    //Depth at the clipped ends, z is linear in screen space after the divide
+    let direction = end.truncate() - start.truncate();
+    let length_squared = direction.length_squared().max(f32::EPSILON);
+    let depth_at = |point: Vec2| -> f32 {
+        let t = ((point - start.truncate()).dot(direction) / length_squared).clamp(0.0, 1.0);
+        crate::math::lerp(start.z, end.z, t)
+    };
+
+    let start_depth = depth_at(clipped_line.start);
+    let end_depth = depth_at(clipped_line.end);
+
+    let clipped_start = clipped_line.start.as_uvec2();
+    let clipped_end = clipped_line.end.as_uvec2();
+
+    let mut x = clipped_start.x as i32;
+    let mut y = clipped_start.y as i32;
+
+    let final_x = clipped_end.x as i32;
+    let final_y = clipped_end.y as i32;
+
+    let dx = (final_x - x).abs();
+    let dy = -(final_y - y).abs();
+
+    let sx = if x < final_x { 1 } else { -1 };
+    let sy = if y < final_y { 1 } else { -1 };
+
+    //Thick lines are drawn as spans across the minor axis
+    let x_major = dx >= -dy;
+    let steps = dx.max(-dy).max(1) as f32;
+    let span_start = -((width.max(1) as i32 - 1) / 2);
+    let span_end = span_start + width.max(1) as i32;
+
+    let mut error = dy + dx;
+    let mut step = 0;
+
+    loop {
+        let depth = crate::math::lerp(start_depth, end_depth, step as f32 / steps);
+
+        for offset in span_start..span_end {
+            let (px, py) = if x_major { (x, y + offset) } else { (x + offset, y) };
+
+            if px >= 0 && py >= 0 && (px as usize) < target_width && (py as usize) < target_height {
+                plot(px as usize, py as usize, depth);
+            }
+        }
+
+        if x == final_x && y == final_y { break };
+        let e2 = 2 * error;
+
+        if e2 >= dy {
+            error += dy;
+            x += sx;
+        }
+
+        if e2 <= dx {
+            error += dx;
+            y += sy;
+        }
+
+        step += 1;
+    }
+}
+
+//Xiaolin Wu style line with sub-pixel endpoints, generalised to thick lines by
+//treating each column (or row) as a span and measuring how much of every pixel it covers.
+//plot receives (i, j, depth, coverage).
+pub fn rasterize_line_antialiased(target_width: usize, target_height: usize, start: Vec3, end: Vec3, width: f32, mut plot: impl FnMut(usize, usize, f32, f32)) {
+
+    if target_width == 0 || target_height == 0 { return; }
+
+    let screen_bounds = BoundingBox::new(
+        glam::UVec2::new(0, 0),
+        glam::UVec2::new(target_width as u32 - 1, target_height as u32 - 1)
+    );
+
+    let Some(clipped_line) = screen_bounds.clip_line(&Line::new(start.truncate(), end.truncate())) else { return; };
+
+    let direction = end.truncate() - start.truncate();
+    let length_squared = direction.length_squared().max(f32::EPSILON);
+    let depth_at = |point: Vec2| -> f32 {
+        let t = ((point - start.truncate()).dot(direction) / length_squared).clamp(0.0, 1.0);
+        crate::math::lerp(start.z, end.z, t)
+    };
+
+    let mut p0 = clipped_line.start.extend(depth_at(clipped_line.start));
+    let mut p1 = clipped_line.end.extend(depth_at(clipped_line.end));
+
+    //Walk along the major axis, swapping x and y for steep lines
+    let steep = (p1.y - p0.y).abs() > (p1.x - p0.x).abs();
+    if steep {
+        p0 = Vec3::new(p0.y, p0.x, p0.z);
+        p1 = Vec3::new(p1.y, p1.x, p1.z);
+    }
+
+    if p0.x > p1.x { std::mem::swap(&mut p0, &mut p1); }
+
+    let dx = p1.x - p0.x;
+    let gradient = if dx < f32::EPSILON { 0.0 } else { (p1.y - p0.y) / dx };
+
+    //Thickness measured along the minor axis
+    let half_span = 0.5 * width.max(1.0) * (1.0 + gradient * gradient).sqrt();
+
+    let mut plot_major = |major: i32, minor: i32, depth: f32, coverage: f32| {
+        let (i, j) = if steep { (minor, major) } else { (major, minor) };
+
+        if coverage > 0.0 && i >= 0 && j >= 0 && (i as usize) < target_width && (j as usize) < target_height {
+            plot(i as usize, j as usize, depth, coverage.min(1.0));
+        }
+    };
+
+    let first = p0.x.floor() as i32;
+    let last = p1.x.floor() as i32;
+
+    for x in first..=last {
+
+        //Horizontal coverage, partial at the end columns
+        let column_start = (x as f32).max(p0.x);
+        let column_end = (x as f32 + 1.0).min(p1.x);
+        let x_coverage = if first == last { dx.max(1.0 / 255.0) } else { column_end - column_start };
+
+        let sample_x = (column_start + column_end) * 0.5;
+        let center = p0.y + gradient * (sample_x - p0.x);
+        let t = if dx < f32::EPSILON { 0.0 } else { (sample_x - p0.x) / dx };
+        let depth = crate::math::lerp(p0.z, p1.z, t);
+
+        let (low, high) = (center - half_span, center + half_span);
+
+        for y in (low.floor() as i32)..=(high.floor() as i32) {
+            let y_coverage = (high.min(y as f32 + 1.0) - low.max(y as f32)).clamp(0.0, 1.0);
+            plot_major(x, y, depth, x_coverage * y_coverage);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::{rasterize_line, rasterize_line_antialiased};
+
+    #[test]
+    fn empty_targets_plot_nothing() {
+        let (start, end) = (Vec3::ZERO, Vec3::new(4.0, 4.0, 0.0));
+
+        rasterize_line(0, 0, start, end, 1, |_, _, _| panic!("plotted into an empty target"));
+        rasterize_line_antialiased(0, 4, start, end, 1.0, |_, _, _, _| panic!("plotted into an empty target"));
+
+        let mut plotted = 0;
+        rasterize_line(8, 8, start, end, 1, |_, _, _| plotted += 1);
+        assert!(plotted > 0);
+    }
+}
@@ -1,7 +1,86 @@
+pub mod command;
+pub mod culling;
 pub mod data;
 pub mod debug;
+pub mod debug_draw;
 pub mod vertex;
 pub mod fragment;
+pub mod hiz;
+pub mod hud;
+pub mod line;
+pub mod state;
+pub mod tonemap;
 
+use crate::camera::Camera;
+use crate::scene::Scene;
+use crate::texture::DepthTexture;
+use crate::texture::RenderTarget;
+use crate::texture::Texture;
 
+//Colour can be any target, e.g. a FloatTexture for HDR rendering.
+//With a HiZBuffer, hidden triangles and instances are rejected early
+pub struct RenderTargets<'a, T: RenderTarget = Texture> {
+    pub colour: &'a mut T,
+    pub depth: &'a mut DepthTexture,
+    pub hiz: Option<&'a mut hiz::HiZBuffer>
+}
 
+impl<'a, T: RenderTarget> RenderTargets<'a, T> {
+    pub fn new(colour: &'a mut T, depth: &'a mut DepthTexture) -> Self {
+        Self { colour, depth, hiz: None }
+    }
+
+    pub fn with_hiz(mut self, hiz: &'a mut hiz::HiZBuffer) -> Self {
+        debug_assert!(hiz.tiles() == (self.depth.width().div_ceil(hiz::TILE_SIZE), self.depth.height().div_ceil(hiz::TILE_SIZE)));
+        self.hiz = Some(hiz);
+        self
+    }
+}
+
+//Records the visible nodes of the scene, nodes sharing a mesh
+//and material are recorded as one instanced draw
+pub fn record_scene(scene: &mut Scene, camera: &Camera, commands: &mut command::CommandBuffer) -> culling::CullingStats {
+
+    scene.update_world_matrices();
+
+    let (view, projection) = camera.generate_view_projection();
+    commands.set_uniforms(view, projection);
+
+    let mut culler = culling::FrustumCuller::new(camera);
+
+    let mut draws = Vec::new();
+    scene.visit(|_, node, world| {
+
+        let Some(mesh_id) = node.mesh else { return; };
+        let bounds = &scene.mesh(mesh_id).bounds;
+        if !culler.is_visible(bounds, world) { return; }
+
+        let mesh_id = scene.select_lod(mesh_id, camera.projected_size(&bounds.sphere.transform(world)));
+
+        draws.push((mesh_id, node.material, data::InstanceData::from_model(*world)));
+    });
+
+    draws.sort_by_key(|(mesh, material, _)| (*mesh, *material));
+
+    for batch in draws.chunk_by(|a, b| a.0 == b.0 && a.1 == b.1) {
+
+        let (mesh_id, material_id, _) = batch[0];
+        let instances = batch.iter().map(|(_, _, instance)| *instance).collect();
+
+        let material = material_id.map_or(scene.default_material(), |id| scene.material(id));
+        commands.bind_texture(material.texture.clone(), material.sampler);
+        commands.draw_indexed_instanced(scene.mesh(mesh_id).clone(), instances);
+    }
+
+    culler.stats
+}
+
+//Walks the scene graph and draws every visible node with a mesh
+pub fn render<T: RenderTarget>(scene: &mut Scene, camera: &Camera, targets: &mut RenderTargets<T>) -> culling::CullingStats {
+
+    let mut commands = command::CommandBuffer::default();
+    let stats = record_scene(scene, camera, &mut commands);
+
+    commands.submit(targets);
+    stats
+}
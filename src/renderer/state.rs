@@ -0,0 +1,209 @@
+//Fixed function state, bundled together by command::PipelineState
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CullMode {
+    #[default]
+    None,
+    Back,
+    Front
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FillMode {
+    #[default]
+    Solid,
+    Wireframe,
+    SolidWireframe
+}
+
+//Front faces are counter clockwise in NDC
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RasterState {
+    pub cull_mode: CullMode,
+    pub fill_mode: FillMode,
+    //Edge thickness in pixels and RGB colour for the wireframe modes
+    pub wireframe_width: f32,
+    pub wireframe_colour: glam::Vec3,
+    //In pixels, for the line topologies
+    pub line_width: u32
+}
+
+impl Default for RasterState {
+    fn default() -> Self {
+        Self { cull_mode: CullMode::None, fill_mode: FillMode::Solid, wireframe_width: 1.0, wireframe_colour: glam::Vec3::ONE, line_width: 1 }
+    }
+}
+
+impl RasterState {
+    pub fn should_cull(&self, ndc_area: f32) -> bool {
+        match self.cull_mode {
+            CullMode::None => false,
+            CullMode::Back => ndc_area <= 0.0,
+            CullMode::Front => ndc_area >= 0.0
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PointShape {
+    #[default]
+    Square,
+    Round
+}
+
+//Sprites replace the vertex uvs with the position inside the point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointState {
+    //In pixels
+    pub size: f32,
+    pub shape: PointShape,
+    pub sprite: bool
+}
+
+impl Default for PointState {
+    fn default() -> Self {
+        Self { size: 1.0, shape: PointShape::Square, sprite: false }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CompareFunction {
+    Never,
+    #[default]
+    Less,
+    LessEqual,
+    Equal,
+    GreaterEqual,
+    Greater,
+    Always
+}
+
+impl CompareFunction {
+    pub fn passes(&self, value: f32, reference: f32) -> bool {
+        match self {
+            CompareFunction::Never => false,
+            CompareFunction::Less => value < reference,
+            CompareFunction::LessEqual => value <= reference,
+            CompareFunction::Equal => value == reference,
+            CompareFunction::GreaterEqual => value >= reference,
+            CompareFunction::Greater => value > reference,
+            CompareFunction::Always => true
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthState {
+    pub test: bool,
+    pub write: bool,
+    pub compare: CompareFunction
+}
+
+impl Default for DepthState {
+    fn default() -> Self {
+        Self { test: true, write: true, compare: CompareFunction::Less }
+    }
+}
+
+impl DepthState {
+    pub fn passes(&self, depth: f32, stored: f32) -> bool {
+        !self.test || self.compare.passes(depth, stored)
+    }
+
+    //Ranges are (min, max). True when no depth in the first range can pass against any stored one
+    pub fn fails_range(&self, depth: (f32, f32), stored: (f32, f32)) -> bool {
+        if !self.test { return false; }
+        match self.compare {
+            CompareFunction::Never => true,
+            CompareFunction::Less => depth.0 >= stored.1,
+            CompareFunction::LessEqual => depth.0 > stored.1,
+            CompareFunction::Equal => depth.1 < stored.0 || depth.0 > stored.1,
+            CompareFunction::GreaterEqual => depth.1 < stored.0,
+            CompareFunction::Greater => depth.1 <= stored.0,
+            CompareFunction::Always => false
+        }
+    }
+
+    //True when every depth in the first range passes against every stored one
+    pub fn passes_range(&self, depth: (f32, f32), stored: (f32, f32)) -> bool {
+        if !self.test { return true; }
+        match self.compare {
+            CompareFunction::Never | CompareFunction::Equal => false,
+            CompareFunction::Less => depth.1 < stored.0,
+            CompareFunction::LessEqual => depth.1 <= stored.0,
+            CompareFunction::GreaterEqual => depth.0 >= stored.1,
+            CompareFunction::Greater => depth.0 > stored.1,
+            CompareFunction::Always => true
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    #[default]
+    Opaque,
+    Alpha,
+    Additive
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BlendState {
+    pub mode: BlendMode
+}
+
+impl BlendState {
+    pub fn is_opaque(&self) -> bool { self.mode == BlendMode::Opaque }
+
+    //Both colours are RGBA
+    pub fn blend(&self, source: glam::Vec4, destination: glam::Vec4) -> glam::Vec4 {
+        match self.mode {
+            BlendMode::Opaque => source,
+            BlendMode::Alpha => {
+                let colour = crate::math::lerp(destination.truncate(), source.truncate(), source.w);
+                colour.extend(source.w + destination.w * (1.0 - source.w))
+            }
+            BlendMode::Additive => {
+                (destination.truncate() + source.truncate() * source.w).extend(destination.w)
+            }
+        }
+    }
+}
+
+//Region of the render target in pixels, origin at the top left
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize
+}
+
+impl Viewport {
+
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self { x, y, width, height }
+    }
+
+    pub fn from_size(width: usize, height: usize) -> Self {
+        Self { x: 0, y: 0, width, height }
+    }
+
+    pub fn screen_space_matrix(&self) -> glam::Mat3 {
+        let half_width = (self.width as f32) * 0.5;
+        let half_height = (self.height as f32) * 0.5;
+
+        glam::Mat3::from_scale_angle_translation(
+            glam::Vec2::new(half_width, -half_height),
+            0.0,
+            glam::Vec2::new(self.x as f32 + half_width, self.y as f32 + half_height)
+        )
+    }
+
+    //Clamped to the target, end is exclusive
+    pub fn bounds(&self, target_width: usize, target_height: usize) -> crate::math::bounding_box::BoundingBox {
+        crate::math::bounding_box::BoundingBox::new(
+            glam::UVec2::new(self.x as u32, self.y as u32),
+            glam::UVec2::new((self.x + self.width).min(target_width) as u32, (self.y + self.height).min(target_height) as u32)
+        )
+    }
+}
@@ -0,0 +1,240 @@
+use glam::Vec3;
+
+use crate::texture::FloatTexture;
+use crate::texture::RenderTarget;
+
+const HISTOGRAM_BINS: usize = 64;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ToneMapOperator {
+    //Plain clamp to 0-1
+    None,
+    Reinhard,
+    //Maps white_point to 1.0
+    ExtendedReinhard { white_point: f32 },
+    //Krzysztof Narkowicz's fit of the ACES curve
+    #[default]
+    AcesFitted,
+    //John Hable's filmic curve from Uncharted 2
+    Uncharted2 { white_point: f32 }
+}
+
+impl ToneMapOperator {
+    pub fn apply(&self, colour: Vec3) -> Vec3 {
+        match *self {
+            ToneMapOperator::None => colour,
+            ToneMapOperator::Reinhard => colour / (Vec3::ONE + colour),
+            ToneMapOperator::ExtendedReinhard { white_point } => {
+                colour * (Vec3::ONE + colour / (white_point * white_point)) / (Vec3::ONE + colour)
+            }
+            ToneMapOperator::AcesFitted => {
+                let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+                (colour * (colour * a + b)) / (colour * (colour * c + d) + e)
+            }
+            ToneMapOperator::Uncharted2 { white_point } => {
+                let exposure_bias = 2.0;
+                uncharted2_partial(colour * exposure_bias) / uncharted2_partial(Vec3::splat(white_point))
+            }
+        }.clamp(Vec3::ZERO, Vec3::ONE)
+    }
+}
+
+fn uncharted2_partial(x: Vec3) -> Vec3 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (x * a + c * b) + d * e) / (x * (x * a + b) + d * f)) - e / f
+}
+
+//Exposure settings are in stops, 0.0 leaves the image unchanged
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exposure {
+    Manual(f32),
+    Automatic(AutoExposure)
+}
+
+impl Default for Exposure {
+    fn default() -> Self { Exposure::Manual(0.0) }
+}
+
+//Average scene luminance is measured from a log2 histogram, ignoring the darkest
+//and brightest pixels, and the exposure adapts towards it over time.
+//The defaults average the brighter half of the image below the top 5%, so large dark
+//backgrounds don't push the exposure up and blow out what's lit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoExposure {
+    pub min_log_luminance: f32,
+    pub max_log_luminance: f32,
+    //Cumulative fractions of pixels, darkest first, bounding the averaged range
+    pub low_percentile: f32,
+    pub high_percentile: f32,
+    //Luminance the average is mapped to
+    pub key: f32,
+    //Per second
+    pub adaptation_speed: f32,
+    pub compensation: f32
+}
+
+impl Default for AutoExposure {
+    fn default() -> Self {
+        Self {
+            min_log_luminance: -10.0,
+            max_log_luminance: 10.0,
+            low_percentile: 0.5,
+            high_percentile: 0.95,
+            key: 0.18,
+            adaptation_speed: 2.0,
+            compensation: 0.0
+        }
+    }
+}
+
+pub fn luminance(colour: Vec3) -> f32 {
+    colour.dot(Vec3::new(0.2126, 0.7152, 0.0722))
+}
+
+//Converts HDR render targets into presentable ones
+#[derive(Debug, Default)]
+pub struct ToneMapper {
+    pub operator: ToneMapOperator,
+    pub exposure: Exposure,
+    //Adapted average luminance, None until the first automatic frame
+    adapted_luminance: Option<f32>
+}
+
+impl ToneMapper {
+
+    pub fn new(operator: ToneMapOperator, exposure: Exposure) -> Self {
+        Self { operator, exposure, adapted_luminance: None }
+    }
+
+    pub fn adapted_luminance(&self) -> Option<f32> { self.adapted_luminance }
+
+    //Linear scale applied before the operator
+    pub fn exposure_scale(&self) -> f32 {
+        match self.exposure {
+            Exposure::Manual(stops) => 2.0f32.powf(stops),
+            Exposure::Automatic(settings) => {
+                let average = self.adapted_luminance.unwrap_or(settings.key).max(f32::EPSILON);
+                settings.key / average * 2.0f32.powf(settings.compensation)
+            }
+        }
+    }
+
+    pub fn luminance_histogram(hdr: &FloatTexture, settings: &AutoExposure) -> [u32; HISTOGRAM_BINS] {
+
+        let mut histogram = [0u32; HISTOGRAM_BINS];
+        let range = settings.max_log_luminance - settings.min_log_luminance;
+
+        for texel in hdr.as_slice() {
+            let log_luminance = luminance(texel.truncate()).max(f32::MIN_POSITIVE).log2();
+            let normalized = ((log_luminance - settings.min_log_luminance) / range).clamp(0.0, 1.0);
+            let bin = ((normalized * HISTOGRAM_BINS as f32) as usize).min(HISTOGRAM_BINS - 1);
+            histogram[bin] += 1;
+        }
+
+        histogram
+    }
+
+    //Mean luminance of the pixels between the two percentiles
+    pub fn average_luminance(histogram: &[u32; HISTOGRAM_BINS], settings: &AutoExposure) -> f32 {
+
+        let total: u32 = histogram.iter().sum();
+        if total == 0 { return settings.key; }
+
+        let low = settings.low_percentile * total as f32;
+        let high = settings.high_percentile * total as f32;
+        let bin_width = (settings.max_log_luminance - settings.min_log_luminance) / HISTOGRAM_BINS as f32;
+
+        let mut seen = 0.0;
+        let mut weighted_sum = 0.0;
+        let mut weight = 0.0;
+
+        for (bin, count) in histogram.iter().enumerate() {
+
+            //Part of this bin that falls between the percentiles
+            let count = *count as f32;
+            let start = seen;
+            seen += count;
+            let used = (seen.min(high) - start.max(low)).max(0.0);
+
+            let log_luminance = settings.min_log_luminance + (bin as f32 + 0.5) * bin_width;
+            weighted_sum += log_luminance * used;
+            weight += used;
+        }
+
+        if weight <= 0.0 { return settings.key; }
+        2.0f32.powf(weighted_sum / weight)
+    }
+
+    //Updates automatic exposure by dt seconds and tone maps hdr into out
+    pub fn resolve<T: RenderTarget>(&mut self, hdr: &FloatTexture, out: &mut T, dt: f32) {
+
+        debug_assert!(hdr.width() == out.width());
+        debug_assert!(hdr.height() == out.height());
+
+        if let Exposure::Automatic(settings) = self.exposure {
+            let histogram = ToneMapper::luminance_histogram(hdr, &settings);
+            let target = ToneMapper::average_luminance(&histogram, &settings);
+
+            let adapted = match self.adapted_luminance {
+                Some(previous) => previous + (target - previous) * (1.0 - (-dt * settings.adaptation_speed).exp()),
+                None => target
+            };
+            self.adapted_luminance = Some(adapted);
+        }
+
+        let scale = self.exposure_scale();
+
+        for j in 0..hdr.height() {
+            for i in 0..hdr.width() {
+                let texel = hdr.read(i, j);
+                let mapped = self.operator.apply(texel.truncate() * scale);
+                out.write_colour(i, j, mapped.extend(texel.w.clamp(0.0, 1.0)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec3, Vec4};
+
+    use super::{AutoExposure, Exposure, ToneMapOperator, ToneMapper};
+    use crate::texture::FloatTexture;
+
+    #[test]
+    fn operators_stay_in_range() {
+        let operators = [
+            ToneMapOperator::Reinhard,
+            ToneMapOperator::ExtendedReinhard { white_point: 4.0 },
+            ToneMapOperator::AcesFitted,
+            ToneMapOperator::Uncharted2 { white_point: 11.2 }
+        ];
+
+        for operator in operators {
+            let mut previous = -1.0;
+            for value in [0.0, 0.1, 0.5, 1.0, 4.0, 100.0] {
+                let mapped = operator.apply(Vec3::splat(value)).x;
+                assert!((0.0..=1.0).contains(&mapped) && mapped >= previous, "{:?}", operator);
+                previous = mapped;
+            }
+        }
+
+        let white = ToneMapOperator::ExtendedReinhard { white_point: 4.0 }.apply(Vec3::splat(4.0));
+        assert!((white.x - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn auto_exposure_maps_average_to_key() {
+        let settings = AutoExposure { low_percentile: 0.0, high_percentile: 1.0, ..Default::default() };
+        let hdr = FloatTexture::from_data(vec![Vec4::new(8.0, 8.0, 8.0, 1.0); 16], 4, 4);
+
+        let mut tone_mapper = ToneMapper::new(ToneMapOperator::None, Exposure::Automatic(settings));
+        let mut out = FloatTexture::new(4, 4);
+        tone_mapper.resolve(&hdr, &mut out, 0.016);
+
+        //Within half a histogram bin
+        let bin_width = (settings.max_log_luminance - settings.min_log_luminance) / 64.0;
+        let error = (out.read(0, 0).x / settings.key).log2().abs();
+        assert!(error <= bin_width * 0.5 + 1e-4);
+    }
+}
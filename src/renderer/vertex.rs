@@ -1,14 +1,41 @@
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::animation;
 use crate::math;
+use super::data::InstanceData;
 use super::data::VertexInput;
 use super::data::VertexOutput;
 
+//Lookups in the post-transform vertex cache, one per triangle corner
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VertexCacheStats {
+    pub hits: usize,
+    pub misses: usize
+}
+
+impl VertexCacheStats {
+    pub fn hit_rate(&self) -> f32 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 { 0.0 } else { self.hits as f32 / lookups as f32 }
+    }
+}
+
 #[derive(Default)]
 pub struct VertexShader {
     pub view: glam::Mat4,
     pub projection: glam::Mat4,
-    pub model: glam::Mat4
+    pub model: glam::Mat4,
+    //Skinning palette, see animation::Skeleton::joint_matrices. Empty draws vertices unskinned.
+    //Shared with the command that bound it
+    pub joint_matrices: Rc<[glam::Mat4]>,
+    //Accumulates over dispatches until reset
+    cache_stats: Cell<VertexCacheStats>
 }
 
+const NOT_EMITTED: usize = usize::MAX;
+
 impl VertexShader {
 
     fn triangle_indices(input_indices: &[usize], triangle_id: usize) -> [usize; 3] {
@@ -17,65 +44,305 @@ impl VertexShader {
         ]
     }
 
-    fn world_to_clip_space(mvp: &glam::Mat4, positions: &[glam::Vec3; 3]) -> [glam::Vec4; 3] {
-        [
-            mvp.mul_vec4(positions[0].extend(1.0)),
-            mvp.mul_vec4(positions[1].extend(1.0)),
-            mvp.mul_vec4(positions[2].extend(1.0))
-        ]
+    //Linear blend skinning of positions and normals before the model matrix, done once per draw and shared by every instance.
+    //Panics when joints or weights don't cover every vertex, or a joint is outside the palette
+    fn object_space<'a>(&self, vertex_in: &'a VertexInput) -> (Cow<'a, [glam::Vec3]>, Cow<'a, [glam::Vec3]>) {
+        if self.joint_matrices.is_empty() || !vertex_in.has_skin() {
+            return (Cow::Borrowed(&vertex_in.positions), Cow::Borrowed(&vertex_in.normals));
+        }
+
+        let (positions, normals) = animation::skin_positions_and_normals(vertex_in, &self.joint_matrices);
+        (Cow::Owned(positions), Cow::Owned(normals))
     }
 
+    //World space normal of an input vertex, zero for meshes without normals
+    fn world_normal(normals: &[glam::Vec3], index: usize, normal_matrix: glam::Mat3) -> glam::Vec3 {
+        normals.get(index).map_or(glam::Vec3::ZERO, |normal| (normal_matrix * *normal).normalize_or_zero())
+    }
+
+    pub fn cache_stats(&self) -> VertexCacheStats { self.cache_stats.get() }
+    pub fn reset_cache_stats(&self) { self.cache_stats.set(VertexCacheStats::default()); }
+
     pub fn dispatch(&self, vertex_in: &VertexInput, indices: &[usize]) -> (VertexOutput, Vec<usize>) {
+        self.dispatch_instanced(vertex_in, indices, &[InstanceData::from_model(self.model)])
+    }
+
+    //Draws the mesh once per instance, self.model is ignored.
+    //Vertices are transformed once per instance and shared by every unclipped triangle using them,
+    //only clipping creates new output vertices
+    pub fn dispatch_instanced(&self, vertex_in: &VertexInput, indices: &[usize], instances: &[InstanceData]) -> (VertexOutput, Vec<usize>) {
 
         let input_triangle_count = indices.len() / 3;
+        let vertex_count = vertex_in.positions.len();
+        let (positions, normals) = self.object_space(vertex_in);
 
         //Outputs
         let mut out_indices = Vec::new();
         let mut out_vertex = VertexOutput::default();
 
-        //VP and Frustrum
-        let mvp = self.projection * self.view * self.model;
+        //Post-transform cache, clip space position and output vertex per input vertex
+        let mut clip_cache: Vec<Option<glam::Vec4>> = vec![None; vertex_count];
+        let mut emitted: Vec<usize> = vec![NOT_EMITTED; vertex_count];
+        let mut stats = self.cache_stats.get();
+
+        let vp = self.projection * self.view;
+
+        for (instance_id, instance) in instances.iter().enumerate() {
+
+            let mvp = vp * instance.model;
+            let normal_matrix = animation::normal_matrix(instance.model);
+            clip_cache.fill(None);
+            emitted.fill(NOT_EMITTED);
+
+            for i in 0..input_triangle_count {
+
+                let triangle_indices = VertexShader::triangle_indices(indices, i);
 
-        //Main body
-        let mut triangle_start = 0;
-        for i in 0..input_triangle_count {
+                let clip_coordinates = triangle_indices.map(|index| {
+                    match clip_cache[index] {
+                        Some(position) => { stats.hits += 1; position }
+                        None => {
+                            stats.misses += 1;
+                            let position = mvp.mul_vec4(positions[index].extend(1.0));
+                            clip_cache[index] = Some(position);
+                            position
+                        }
+                    }
+                });
 
-            let triangle_indices = VertexShader::triangle_indices(indices, i);
-            let vertices = VertexInput::retrieve(&vertex_in.positions, triangle_indices);
-            let clip_coordinates = VertexShader::world_to_clip_space(&mvp, &vertices);
+                //Frustum clipping
+                if math::should_cull_triangle(clip_coordinates[0], clip_coordinates[1], clip_coordinates[2]) { continue; }
 
-            //Frustum clipping
-            if math::should_cull_triangle(clip_coordinates[0], clip_coordinates[1], clip_coordinates[2]) { continue; }         
+                //Guard band clipping, most triangles that cross the screen edges skip the clipper
+                if math::triangle_in_guard_band(clip_coordinates[0], clip_coordinates[1], clip_coordinates[2]) {
+                    for (index, position) in triangle_indices.iter().zip(clip_coordinates) {
+                        if emitted[*index] == NOT_EMITTED {
+                            emitted[*index] = out_vertex.ndc_positions.len();
+                            let normal = VertexShader::world_normal(&normals, *index, normal_matrix);
+                            out_vertex.push(position, vertex_in.colours[*index], vertex_in.uvs[*index], normal, instance_id as u32);
+                        }
+                        out_indices.push(emitted[*index]);
+                    }
+                    continue;
+                }
 
-            let clipped_vertices = math::clip_homogenous_triangle(&clip_coordinates);
-            if clipped_vertices.is_empty() { continue; }
+                let clipped_vertices = math::clip_guard_band_triangle(&clip_coordinates);
+                if clipped_vertices.len() < 3 { continue; }
 
-            for (vert, bary) in &clipped_vertices {
-                
-                let inv_depth = 1.0 / vert.w;
-                out_vertex.ndc_positions.push((*vert * inv_depth).truncate().extend(inv_depth));
+                let colours = triangle_indices.map(|index| vertex_in.colours[index]);
+                let uvs = triangle_indices.map(|index| vertex_in.uvs[index]);
+                let triangle_normals = triangle_indices.map(|index| VertexShader::world_normal(&normals, index, normal_matrix));
+                let first = out_vertex.ndc_positions.len();
 
-                let colours = VertexInput::retrieve(&vertex_in.colours, triangle_indices);
-                let uvs = VertexInput::retrieve(&vertex_in.uvs, triangle_indices);
+                for (vert, bary) in &clipped_vertices {
+                    let colour = math::barycentric_lerp(*bary, colours[0], colours[1], colours[2]);
+                    let uv = math::barycentric_lerp(*bary, uvs[0], uvs[1], uvs[2]);
+                    let normal = math::barycentric_lerp(*bary, triangle_normals[0], triangle_normals[1], triangle_normals[2]);
+                    out_vertex.push(*vert, colour, uv, normal, instance_id as u32);
+                }
 
-                let result_colour = math::barycentric_lerp(*bary, colours[0], colours[1],colours[2]);
-                let result_uv = math::barycentric_lerp(*bary, uvs[0], uvs[1],uvs[2]);
-                out_vertex.colours.push(result_colour * inv_depth);
-                out_vertex.uvs.push(result_uv * inv_depth);
+                //Fan triangulation
+                for v in 1..clipped_vertices.len() - 1 {
+                    out_indices.extend_from_slice(&[first, first + v, first + v + 1]);
+                }
             }
+        }
+
+        self.cache_stats.set(stats);
+        (out_vertex, out_indices)
+    }
+
+    pub fn dispatch_lines(&self, vertex_in: &VertexInput, indices: &[usize]) -> (VertexOutput, Vec<usize>) {
+        self.dispatch_lines_instanced(vertex_in, indices, &[InstanceData::from_model(self.model)])
+    }
+
+    //Every pair of indices is a line, clipped to near, far and the guard band like triangles
+    pub fn dispatch_lines_instanced(&self, vertex_in: &VertexInput, indices: &[usize], instances: &[InstanceData]) -> (VertexOutput, Vec<usize>) {
+
+        let mut out_indices = Vec::new();
+        let mut out_vertex = VertexOutput::default();
+
+        let (positions, normals) = self.object_space(vertex_in);
+        let vp = self.projection * self.view;
 
-            let triangulation_indices: Vec<(usize, usize, usize)> = (1..clipped_vertices.len() - 1)
-                .map(|v| { (0, v, v + 1) }).collect();
+        for (instance_id, instance) in instances.iter().enumerate() {
 
-            for elem in &triangulation_indices {
-                out_indices.push(triangle_start + elem.0);
-                out_indices.push(triangle_start + elem.1);
-                out_indices.push(triangle_start + elem.2);
+            let mvp = vp * instance.model;
+            let normal_matrix = animation::normal_matrix(instance.model);
+
+            for line in indices.chunks_exact(2) {
+
+                let (start, end) = (line[0], line[1]);
+                let clip_start = mvp.mul_vec4(positions[start].extend(1.0));
+                let clip_end = mvp.mul_vec4(positions[end].extend(1.0));
+
+                let Some((clipped_start, clipped_end, t_start, t_end)) = math::clip_homogenous_line(clip_start, clip_end, &math::GUARD_BAND_PLANES) else { continue; };
+
+                for (position, t) in [(clipped_start, t_start), (clipped_end, t_end)] {
+                    let colour = math::lerp(vertex_in.colours[start], vertex_in.colours[end], t);
+                    let uv = math::lerp(vertex_in.uvs[start], vertex_in.uvs[end], t);
+                    let normal = math::lerp(
+                        VertexShader::world_normal(&normals, start, normal_matrix), VertexShader::world_normal(&normals, end, normal_matrix), t
+                    );
+
+                    out_indices.push(out_vertex.ndc_positions.len());
+                    out_vertex.push(position, colour, uv, normal, instance_id as u32);
+                }
             }
+        }
+
+        (out_vertex, out_indices)
+    }
+
+    pub fn dispatch_points(&self, vertex_in: &VertexInput, indices: &[usize]) -> (VertexOutput, Vec<usize>) {
+        self.dispatch_points_instanced(vertex_in, indices, &[InstanceData::from_model(self.model)])
+    }
+
+    //Points outside any of the clip planes are dropped, the rest are passed through
+    pub fn dispatch_points_instanced(&self, vertex_in: &VertexInput, indices: &[usize], instances: &[InstanceData]) -> (VertexOutput, Vec<usize>) {
+
+        let mut out_indices = Vec::new();
+        let mut out_vertex = VertexOutput::default();
+
+        let (positions, normals) = self.object_space(vertex_in);
+        let vp = self.projection * self.view;
+        let instance_mvps: Vec<(glam::Mat4, glam::Mat3)> = instances.iter()
+            .map(|instance| (vp * instance.model, animation::normal_matrix(instance.model)))
+            .collect();
 
-            triangle_start += clipped_vertices.len();
+        for index in indices {
+
+            let position = positions[*index].extend(1.0);
+
+            for (instance_id, (mvp, normal_matrix)) in instance_mvps.iter().enumerate() {
+
+                let clip_coordinates = mvp.mul_vec4(position);
+                if math::CLIP_PLANES.iter().any(|plane| plane.dot(clip_coordinates) < 0.0) { continue; }
+
+                out_indices.push(out_vertex.ndc_positions.len());
+                let normal = VertexShader::world_normal(&normals, *index, *normal_matrix);
+                out_vertex.push(clip_coordinates, vertex_in.colours[*index], vertex_in.uvs[*index], normal, instance_id as u32);
+            }
         }
 
         (out_vertex, out_indices)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Vec2, Vec3};
+
+    use super::{VertexCacheStats, VertexShader};
+    use crate::renderer::data::{InstanceData, VertexInput};
+
+    fn quad(size: f32) -> VertexInput {
+        VertexInput {
+            positions: vec![Vec3::new(-size, size, 0.5), Vec3::new(-size, -size, 0.5), Vec3::new(size, -size, 0.5), Vec3::new(size, size, 0.5)],
+            colours: vec![Vec3::ONE; 4],
+            uvs: vec![Vec2::ZERO; 4],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn shared_vertices_are_transformed_and_emitted_once() {
+        let vs = VertexShader { view: Mat4::IDENTITY, projection: Mat4::IDENTITY, ..Default::default() };
+        let indices = [0, 1, 2, 2, 3, 0];
+
+        let (vertices, out_indices) = vs.dispatch(&quad(0.5), &indices);
+        assert_eq!(vertices.ndc_positions.len(), 4);
+        assert_eq!(out_indices, indices);
+        assert_eq!(vs.cache_stats(), VertexCacheStats { hits: 2, misses: 4 });
+
+        //The cache starts over for every instance
+        let instances = [InstanceData::default(), InstanceData::default()];
+        let (vertices, out_indices) = vs.dispatch_instanced(&quad(0.5), &indices, &instances);
+        assert_eq!(vertices.ndc_positions.len(), 8);
+        assert_eq!(out_indices[6..], [4, 5, 6, 6, 7, 4]);
+        assert_eq!(vs.cache_stats(), VertexCacheStats { hits: 6, misses: 12 });
+        assert!((vs.cache_stats().hit_rate() - 1.0 / 3.0).abs() < 1e-6);
+
+        vs.reset_cache_stats();
+        assert_eq!(vs.cache_stats().hit_rate(), 0.0);
+    }
+
+    #[test]
+    fn only_clipped_triangles_emit_new_vertices() {
+        let vs = VertexShader { view: Mat4::IDENTITY, projection: Mat4::IDENTITY, ..Default::default() };
+
+        //Past the guard band on every side, each triangle is clipped to the band on its own
+        let (vertices, out_indices) = vs.dispatch(&quad(20.0), &[0, 1, 2, 2, 3, 0]);
+        assert!(vertices.ndc_positions.len() > 4);
+        assert!(vertices.ndc_positions.iter().all(|p| p.x.abs() <= 8.0 + 1e-4 && p.y.abs() <= 8.0 + 1e-4));
+        assert_eq!(out_indices.len() % 3, 0);
+        assert_eq!(vs.cache_stats(), VertexCacheStats { hits: 2, misses: 4 });
+    }
+
+    #[test]
+    fn joints_move_vertices_before_the_model_matrix() {
+        let mut skinned = quad(0.5);
+        skinned.joints = vec![[0, 1, 0, 0]; 4];
+        skinned.weights = vec![glam::Vec4::new(0.0, 1.0, 0.0, 0.0); 4];
+
+        let vs = VertexShader {
+            view: Mat4::IDENTITY,
+            projection: Mat4::IDENTITY,
+            joint_matrices: vec![Mat4::IDENTITY, Mat4::from_translation(Vec3::X * 0.25)].into(),
+            ..Default::default()
+        };
+        let instances = [InstanceData::from_model(Mat4::from_scale(Vec3::splat(2.0)))];
+
+        let (vertices, _) = vs.dispatch_instanced(&skinned, &[0, 1, 2], &instances);
+        assert!((vertices.ndc_positions[0].x - -0.5).abs() < 1e-6);
+
+        //Vertices without joints ignore the palette
+        let (vertices, _) = vs.dispatch_instanced(&quad(0.5), &[0, 1, 2], &instances);
+        assert!((vertices.ndc_positions[0].x - -1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn skinned_normals_reach_the_output_in_world_space() {
+        let mut skinned = quad(0.5);
+        skinned.normals = vec![Vec3::Z; 4];
+        skinned.joints = vec![[0, 0, 0, 0]; 4];
+        skinned.weights = vec![glam::Vec4::X; 4];
+
+        let vs = VertexShader {
+            view: Mat4::IDENTITY,
+            projection: Mat4::IDENTITY,
+            joint_matrices: vec![Mat4::from_rotation_y(std::f32::consts::FRAC_PI_2)].into(),
+            ..Default::default()
+        };
+        let instances = [InstanceData::from_model(Mat4::from_rotation_z(std::f32::consts::FRAC_PI_2))];
+
+        //The joint turns z to x, the model turns x to y. Attributes are stored over w, which is 1 here
+        let (vertices, _) = vs.dispatch_instanced(&skinned, &[0, 1, 2], &instances);
+        assert!(vertices.normals.iter().all(|normal| normal.abs_diff_eq(Vec3::Y, 1e-6)), "{:?}", vertices.normals);
+
+        let (vertices, _) = vs.dispatch_instanced(&quad(0.5), &[0, 1, 2], &instances);
+        assert!(vertices.normals.iter().all(|normal| *normal == Vec3::ZERO));
+    }
+
+    #[test]
+    #[should_panic(expected = "joint 1 is outside the 1 joint matrices")]
+    fn joints_past_the_palette_panic_clearly() {
+        let mut skinned = quad(0.5);
+        skinned.joints = vec![[1, 0, 0, 0]; 4];
+        skinned.weights = vec![glam::Vec4::X; 4];
+
+        let vs = VertexShader { joint_matrices: vec![Mat4::IDENTITY].into(), ..Default::default() };
+        vs.dispatch(&skinned, &[0, 1, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "need joints and weights for every vertex")]
+    fn joints_missing_for_some_vertices_panic_clearly() {
+        let mut skinned = quad(0.5);
+        skinned.joints = vec![[0, 0, 0, 0]; 2];
+        skinned.weights = vec![glam::Vec4::X; 2];
+
+        let vs = VertexShader { joint_matrices: vec![Mat4::IDENTITY].into(), ..Default::default() };
+        vs.dispatch(&skinned, &[0, 1, 2]);
+    }
+}
@@ -0,0 +1,303 @@
+use std::rc::Rc;
+
+use glam::Mat4;
+use glam::Quat;
+use glam::Vec3;
+
+use crate::renderer::data::Mesh;
+use crate::texture::Sampler;
+use crate::texture::Texture;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self { translation: Vec3::ZERO, rotation: Quat::IDENTITY, scale: Vec3::ONE }
+    }
+}
+
+impl Transform {
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self { translation, ..Default::default() }
+    }
+
+    pub fn from_rotation(rotation: Quat) -> Self {
+        Self { rotation, ..Default::default() }
+    }
+
+    pub fn from_scale(scale: Vec3) -> Self {
+        Self { scale, ..Default::default() }
+    }
+
+    pub fn to_matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MeshId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MaterialId(usize);
+
+//A simpler version of a mesh, drawn while the mesh covers at most max_screen_size of the screen height
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lod {
+    pub mesh: MeshId,
+    pub max_screen_size: f32
+}
+
+pub struct Material {
+    pub texture: Rc<Texture>,
+    pub sampler: Sampler
+}
+
+impl Default for Material {
+    //Plain white so untextured meshes still show their vertex colours
+    fn default() -> Self {
+        Self { texture: Rc::new(Texture::solid(0xFFFFFFFF)), sampler: Sampler::default() }
+    }
+}
+
+pub struct Node {
+    pub name: String,
+    pub mesh: Option<MeshId>,
+    pub material: Option<MaterialId>,
+    local: Transform,
+    world: Mat4,
+    dirty: bool,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>
+}
+
+impl Node {
+    pub fn local_transform(&self) -> &Transform { &self.local }
+    pub fn parent(&self) -> Option<NodeId> { self.parent }
+    pub fn children(&self) -> &[NodeId] { &self.children }
+}
+
+#[derive(Default)]
+pub struct Scene {
+    nodes: Vec<Node>,
+    meshes: Vec<Rc<Mesh>>,
+    //Per mesh, from most to least detailed
+    lods: Vec<Vec<Lod>>,
+    materials: Vec<Material>,
+    //Drawn for nodes without a material, made once so every frame shares its texture
+    default_material: Material
+}
+
+impl Scene {
+
+    pub fn add_mesh(&mut self, mesh: Mesh) -> MeshId {
+        self.meshes.push(Rc::new(mesh));
+        self.lods.push(Vec::new());
+        MeshId(self.meshes.len() - 1)
+    }
+
+    pub fn add_material(&mut self, material: Material) -> MaterialId {
+        self.materials.push(material);
+        MaterialId(self.materials.len() - 1)
+    }
+
+    pub fn add_node(&mut self, name: &str, parent: Option<NodeId>, local: Transform) -> NodeId {
+
+        let id = NodeId(self.nodes.len());
+
+        self.nodes.push(Node {
+            name: name.to_string(),
+            mesh: None,
+            material: None,
+            local,
+            world: Mat4::IDENTITY,
+            dirty: true,
+            parent,
+            children: Vec::new()
+        });
+
+        if let Some(parent) = parent {
+            self.nodes[parent.0].children.push(id);
+        }
+
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node { &self.nodes[id.0] }
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node { &mut self.nodes[id.0] }
+    pub fn mesh(&self, id: MeshId) -> &Rc<Mesh> { &self.meshes[id.0] }
+    pub fn material(&self, id: MaterialId) -> &Material { &self.materials[id.0] }
+    pub fn default_material(&self) -> &Material { &self.default_material }
+
+    pub fn node_count(&self) -> usize { self.nodes.len() }
+
+    pub fn set_lods(&mut self, mesh: MeshId, mut lods: Vec<Lod>) {
+        lods.sort_by(|a, b| b.max_screen_size.total_cmp(&a.max_screen_size));
+        self.lods[mesh.0] = lods;
+    }
+
+    pub fn lods(&self, mesh: MeshId) -> &[Lod] { &self.lods[mesh.0] }
+
+    //The least detailed level allowed at this screen size, see Camera::projected_size
+    pub fn select_lod(&self, mesh: MeshId, screen_size: f32) -> MeshId {
+        self.lods[mesh.0].iter().rev()
+            .find(|lod| screen_size <= lod.max_screen_size)
+            .map_or(mesh, |lod| lod.mesh)
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.nodes.iter().enumerate()
+            .filter(|(_, node)| node.parent.is_none())
+            .map(|(i, _)| NodeId(i))
+    }
+
+    pub fn set_mesh(&mut self, id: NodeId, mesh: MeshId, material: Option<MaterialId>) {
+        let node = &mut self.nodes[id.0];
+        node.mesh = Some(mesh);
+        node.material = material;
+    }
+
+    pub fn set_local_transform(&mut self, id: NodeId, local: Transform) {
+        self.nodes[id.0].local = local;
+        self.mark_dirty(id);
+    }
+
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) {
+
+        debug_assert!(parent.is_none_or(|p| !self.is_ancestor(id, p)), "Parenting would create a cycle");
+
+        if let Some(old_parent) = self.nodes[id.0].parent {
+            self.nodes[old_parent.0].children.retain(|child| *child != id);
+        }
+
+        if let Some(new_parent) = parent {
+            self.nodes[new_parent.0].children.push(id);
+        }
+
+        self.nodes[id.0].parent = parent;
+        self.mark_dirty(id);
+    }
+
+    //True if ancestor is node or one of its parents
+    fn is_ancestor(&self, ancestor: NodeId, node: NodeId) -> bool {
+        let mut current = Some(node);
+
+        while let Some(id) = current {
+            if id == ancestor { return true; }
+            current = self.nodes[id.0].parent;
+        }
+        false
+    }
+
+    //A dirty node always has dirty descendants, so we can stop early
+    fn mark_dirty(&mut self, id: NodeId) {
+        if self.nodes[id.0].dirty { return; }
+        self.nodes[id.0].dirty = true;
+
+        for i in 0..self.nodes[id.0].children.len() {
+            let child = self.nodes[id.0].children[i];
+            self.mark_dirty(child);
+        }
+    }
+
+    //Only recomputes the matrices that changed since the last call
+    pub fn world_matrix(&mut self, id: NodeId) -> Mat4 {
+
+        if self.nodes[id.0].dirty {
+            let parent_world = match self.nodes[id.0].parent {
+                Some(parent) => self.world_matrix(parent),
+                None => Mat4::IDENTITY
+            };
+
+            let node = &mut self.nodes[id.0];
+            node.world = parent_world * node.local.to_matrix();
+            node.dirty = false;
+        }
+
+        self.nodes[id.0].world
+    }
+
+    pub fn update_world_matrices(&mut self) {
+        for i in 0..self.nodes.len() {
+            self.world_matrix(NodeId(i));
+        }
+    }
+
+    //Depth first walk from the roots, call update_world_matrices first
+    pub fn visit(&self, mut visitor: impl FnMut(NodeId, &Node, &Mat4)) {
+
+        let mut stack: Vec<NodeId> = self.roots().collect();
+        stack.reverse();
+
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id.0];
+            debug_assert!(!node.dirty);
+
+            visitor(id, node, &node.world);
+            stack.extend(node.children.iter().rev());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Vec3};
+
+    use super::{Lod, Scene, Transform};
+    use crate::camera::Camera;
+    use crate::math::bounding_volume::Sphere;
+    use crate::renderer::data::Mesh;
+
+    #[test]
+    fn world_propagation() {
+        let mut scene = Scene::default();
+        let root = scene.add_node("root", None, Transform::from_translation(Vec3::X));
+        let child = scene.add_node("child", Some(root), Transform::from_translation(Vec3::Y));
+
+        let world = scene.world_matrix(child);
+        assert!(world.transform_point3(Vec3::ZERO).abs_diff_eq(Vec3::new(1.0, 1.0, 0.0), 1e-6));
+
+        scene.set_local_transform(root, Transform::from_scale(Vec3::splat(2.0)));
+        let world = scene.world_matrix(child);
+        assert!(world.abs_diff_eq(Mat4::from_scale(Vec3::splat(2.0)) * Mat4::from_translation(Vec3::Y), 1e-6));
+    }
+
+    #[test]
+    fn reparenting() {
+        let mut scene = Scene::default();
+        let a = scene.add_node("a", None, Transform::from_translation(Vec3::X));
+        let b = scene.add_node("b", None, Transform::from_translation(Vec3::Z));
+        let child = scene.add_node("child", Some(a), Transform::default());
+
+        scene.update_world_matrices();
+        scene.set_parent(child, Some(b));
+
+        assert!(scene.node(a).children().is_empty());
+        assert!(scene.world_matrix(child).transform_point3(Vec3::ZERO).abs_diff_eq(Vec3::Z, 1e-6));
+    }
+
+    #[test]
+    fn lods_follow_screen_size() {
+        let mut scene = Scene::default();
+        let [full, half, quarter] = [(); 3].map(|_| scene.add_mesh(Mesh::default()));
+        scene.set_lods(full, vec![Lod { mesh: quarter, max_screen_size: 0.1 }, Lod { mesh: half, max_screen_size: 0.3 }]);
+
+        let camera = Camera { fov: std::f32::consts::FRAC_PI_2, aspect_ratio: 1.0, near: 0.1, far: 100.0, ..Default::default() };
+        let size_at = |distance: f32| camera.projected_size(&Sphere::new(Vec3::new(0.0, 0.0, -distance), 1.0));
+
+        assert!((size_at(4.0) - 0.25).abs() < 1e-6);
+        assert_eq!(size_at(0.5), f32::INFINITY);
+        assert_eq!(scene.select_lod(full, size_at(2.0)), full);
+        assert_eq!(scene.select_lod(full, size_at(4.0)), half);
+        assert_eq!(scene.select_lod(full, size_at(20.0)), quarter);
+        assert_eq!(scene.select_lod(half, size_at(20.0)), half);
+    }
+}
@@ -0,0 +1,309 @@
+use std::path::Path;
+
+use crate::camera::Camera;
+use super::format;
+use super::DepthTexture;
+use super::TexelFormat;
+use super::Texture;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Bmp,
+    Ppm,
+    Tga
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(Self::Png),
+            "bmp" => Some(Self::Bmp),
+            "ppm" => Some(Self::Ppm),
+            "tga" => Some(Self::Tga),
+            _ => None
+        }
+    }
+
+    //Largest width or height the format's header can store
+    pub fn max_dimension(&self) -> usize {
+        match self {
+            Self::Png | Self::Bmp => i32::MAX as usize,
+            Self::Ppm => usize::MAX,
+            Self::Tga => u16::MAX as usize
+        }
+    }
+
+    //rgba is tightly packed, top row first. Empty images and sizes the format can't store are errors
+    pub fn encode(&self, rgba: &[u8], width: usize, height: usize) -> Result<Vec<u8>, String> {
+        debug_assert!(rgba.len() == width * height * 4);
+
+        if width == 0 || height == 0 { return Err(format!("Can't encode an empty {}x{} image", width, height)); }
+        if width.max(height) > self.max_dimension() {
+            return Err(format!("{}x{} is too large for {:?}, the limit is {} per side", width, height, self, self.max_dimension()));
+        }
+
+        match self {
+            Self::Png => Ok(encode_png(rgba, width, height)),
+            Self::Bmp => encode_bmp(rgba, width, height),
+            Self::Ppm => Ok(encode_ppm(rgba, width, height)),
+            Self::Tga => Ok(encode_tga(rgba, width, height))
+        }
+    }
+}
+
+//How depth maps to grey when exporting
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum DepthExport {
+    //Stored depth, most of the range is bunched up near 1
+    #[default]
+    Raw,
+    //View distance, near is black and far is white
+    Linear { near: f32, far: f32 }
+}
+
+impl DepthExport {
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self::Linear { near: camera.near, far: camera.far }
+    }
+}
+
+impl<F: TexelFormat> Texture<F> {
+    //Stored values as 8 bit RGBA, no colour space conversion happens.
+    //Float values are clamped and single channel formats become grey
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.data.len() * 4);
+        for texel in &self.data {
+            let colour = F::to_vec4(*texel);
+            let colour = if F::CHANNELS == 1 { glam::Vec4::new(colour.x, colour.x, colour.x, colour.w) } else { colour };
+            out.extend(colour.to_array().map(|v| (v.clamp(0.0, 1.0) * 255.0 + 0.5) as u8));
+        }
+        out
+    }
+
+    //The format comes from the extension: png, bmp, ppm or tga
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let format = ImageFormat::from_path(path)
+            .ok_or_else(|| format!("Unsupported image extension: {}", path.display()))?;
+        let encoded = format.encode(&self.to_rgba8(), self.width, self.height)?;
+        std::fs::write(path, encoded).map_err(|error| error.to_string())
+    }
+}
+
+impl DepthTexture {
+    //Inverts the perspective projection, 0 at near and 1 at far
+    pub fn linearise(&self, near: f32, far: f32) -> Texture<format::R32F> {
+        let data = self.data.iter().map(|depth| {
+            let view_distance = near * far / (far - depth * (far - near));
+            (view_distance - near) / (far - near)
+        }).collect();
+        Texture::from_data(data, self.width, self.height)
+    }
+
+    pub fn save_depth(&self, path: &Path, export: DepthExport) -> Result<(), String> {
+        match export {
+            DepthExport::Raw => self.save(path),
+            DepthExport::Linear { near, far } => self.linearise(near, far).save(path)
+        }
+    }
+}
+
+fn encode_ppm(rgba: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut out = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    for pixel in rgba.chunks_exact(4) {
+        out.extend_from_slice(&pixel[..3]);
+    }
+    out
+}
+
+//32 bit BGRA, rows stored bottom up
+fn encode_bmp(rgba: &[u8], width: usize, height: usize) -> Result<Vec<u8>, String> {
+    const HEADER_SIZE: u32 = 14 + 40;
+    //The file size has to fit in the header too
+    let image_size = u32::try_from(rgba.len()).ok()
+        .filter(|size| size.checked_add(HEADER_SIZE).is_some())
+        .ok_or_else(|| format!("{}x{} is too large for a BMP file", width, height))?;
+
+    let mut out = Vec::with_capacity((HEADER_SIZE + image_size) as usize);
+    out.extend_from_slice(b"BM");
+    out.extend_from_slice(&(HEADER_SIZE + image_size).to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&HEADER_SIZE.to_le_bytes());
+
+    //BITMAPINFOHEADER
+    out.extend_from_slice(&40u32.to_le_bytes());
+    out.extend_from_slice(&(width as i32).to_le_bytes());
+    out.extend_from_slice(&(height as i32).to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&32u16.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&image_size.to_le_bytes());
+    out.extend_from_slice(&2835i32.to_le_bytes());
+    out.extend_from_slice(&2835i32.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+
+    for row in rgba.chunks_exact(width * 4).rev() {
+        for pixel in row.chunks_exact(4) {
+            out.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+        }
+    }
+    Ok(out)
+}
+
+//Uncompressed 32 bit BGRA, top left origin
+fn encode_tga(rgba: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(18 + rgba.len());
+    out.extend_from_slice(&[0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    out.extend_from_slice(&(width as u16).to_le_bytes());
+    out.extend_from_slice(&(height as u16).to_le_bytes());
+    //32 bits per pixel, 8 of them alpha, rows top to bottom
+    out.extend_from_slice(&[32, 0x28]);
+
+    for pixel in rgba.chunks_exact(4) {
+        out.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+    }
+    out
+}
+
+//RGBA8 without filtering, the zlib stream uses stored deflate blocks
+fn encode_png(rgba: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    //8 bit depth, RGBA, default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    write_png_chunk(&mut out, b"IHDR", &header);
+
+    //Every row starts with its filter type, 0 is none
+    let mut scanlines = Vec::with_capacity(rgba.len() + height);
+    for row in rgba.chunks_exact(width * 4) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    write_png_chunk(&mut out, b"IDAT", &zlib_stored(&scanlines));
+    write_png_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+fn write_png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = u16::MAX as usize;
+
+    //Deflate with a 32K window and no preset dictionary
+    let mut out = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        let length = block.len() as u16;
+        out.push(is_final as u8);
+        out.extend_from_slice(&length.to_le_bytes());
+        out.extend_from_slice(&(!length).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    static TABLE: std::sync::OnceLock<[u32; 256]> = std::sync::OnceLock::new();
+    let table = TABLE.get_or_init(|| std::array::from_fn(|n| {
+        (0..8).fold(n as u32, |c, _| if c & 1 != 0 { 0xEDB88320 ^ (c >> 1) } else { c >> 1 })
+    }));
+
+    !data.iter().fold(!0u32, |crc, byte| table[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    //5552 bytes is the most that can be summed before b overflows
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adler32, crc32, DepthExport, ImageFormat};
+    use crate::texture::{load_image_file, DepthTexture, Texture};
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    }
+
+    #[test]
+    fn saved_images_load_back() {
+        let pixels = vec![0xFFFF0000, 0xFF00FF00, 0xFF0000FF, 0xFFFFFFFF, 0xFF000000, 0xFF808080];
+        let texture: Texture = Texture::from_data(pixels.clone(), 3, 2);
+
+        for extension in ["png", "bmp", "ppm", "tga"] {
+            let path = std::env::temp_dir().join(format!("rusterizer_saved_images_load_back_{}.{}", std::process::id(), extension));
+            texture.save(&path).unwrap();
+
+            let loaded = load_image_file(&path).unwrap();
+            assert_eq!((loaded.width(), loaded.height()), (3, 2), "{}", extension);
+            assert_eq!(loaded.as_slice(), &pixels[..], "{}", extension);
+        }
+
+        assert!(ImageFormat::from_path(std::path::Path::new("image.jpeg")).is_none());
+    }
+
+    #[test]
+    fn unencodable_images_are_errors() {
+        let empty: Texture = Texture::default();
+        assert!(empty.save(&std::env::temp_dir().join(format!("rusterizer_empty_{}.png", std::process::id()))).is_err());
+        assert!(ImageFormat::Bmp.encode(&[], 0, 4).is_err());
+
+        //TGA stores the size in 16 bits
+        let wide = vec![0; (u16::MAX as usize + 1) * 4];
+        assert!(ImageFormat::Tga.encode(&wide, u16::MAX as usize + 1, 1).is_err());
+        assert!(ImageFormat::Png.encode(&wide, u16::MAX as usize + 1, 1).is_ok());
+    }
+
+    #[test]
+    fn depth_linearises_between_near_and_far() {
+        let (near, far) = (0.1, 10.0);
+        let projection = glam::Mat4::perspective_rh(1.0, 1.0, near, far);
+
+        //Halfway between near and far in view space
+        let clip = projection * glam::Vec4::new(0.0, 0.0, -(near + far) * 0.5, 1.0);
+        let depth = DepthTexture::from_data(vec![0.0, clip.z / clip.w, 1.0], 3, 1);
+
+        let linear = depth.linearise(near, far);
+        assert!((linear.read(0, 0)).abs() < 1e-5);
+        assert!((linear.read(1, 0) - 0.5).abs() < 1e-3);
+        assert!((linear.read(2, 0) - 1.0).abs() < 1e-5);
+
+        let path = std::env::temp_dir().join(format!("rusterizer_depth_linearises_{}.png", std::process::id()));
+        depth.save_depth(&path, DepthExport::Linear { near, far }).unwrap();
+        let saved = load_image_file(&path).unwrap();
+        assert_eq!((saved.read(0, 0), saved.read(2, 0)), (0xFF000000, 0xFFFFFFFF));
+    }
+}
@@ -0,0 +1,141 @@
+use crate::math::colour;
+
+//How a texel is stored and converted to and from RGBA.
+//Channels missing from a format read as 0 for colour and 1 for alpha
+pub trait TexelFormat {
+    type Texel: Copy + PartialEq + std::fmt::Debug;
+
+    //Stored channels, single channel formats export as grey
+    const CHANNELS: usize;
+
+    //What new textures are filled with
+    const INITIAL: Self::Texel;
+
+    fn to_vec4(texel: Self::Texel) -> glam::Vec4;
+    fn from_vec4(colour: glam::Vec4) -> Self::Texel;
+
+    //Only 8 bit formats store sRGB, float formats are always linear
+    fn srgb_to_vec4(texel: Self::Texel) -> glam::Vec4 { Self::to_vec4(texel) }
+    fn srgb_from_vec4(colour: glam::Vec4) -> Self::Texel { Self::from_vec4(colour) }
+}
+
+//Packed 0xAARRGGBB, what the window expects
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Argb8;
+
+impl TexelFormat for Argb8 {
+    type Texel = u32;
+    const CHANNELS: usize = 4;
+    const INITIAL: u32 = 0;
+
+    fn to_vec4(texel: u32) -> glam::Vec4 { colour::hex_to_f32(texel) }
+    fn from_vec4(colour: glam::Vec4) -> u32 { colour::vec4_to_hex(colour) }
+    fn srgb_to_vec4(texel: u32) -> glam::Vec4 { colour::srgb_hex_to_linear(texel) }
+    fn srgb_from_vec4(colour: glam::Vec4) -> u32 { colour::linear_to_srgb_hex(colour) }
+}
+
+//Single channel, e.g. masks
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct R8;
+
+impl TexelFormat for R8 {
+    type Texel = u8;
+    const CHANNELS: usize = 1;
+    const INITIAL: u8 = 0;
+
+    fn to_vec4(texel: u8) -> glam::Vec4 { glam::Vec4::new(colour::u8_to_f32(texel), 0.0, 0.0, 1.0) }
+    fn from_vec4(colour: glam::Vec4) -> u8 { colour::f32_to_u8(colour.x) }
+    fn srgb_to_vec4(texel: u8) -> glam::Vec4 { glam::Vec4::new(colour::srgb_u8_to_linear(texel), 0.0, 0.0, 1.0) }
+    fn srgb_from_vec4(colour: glam::Vec4) -> u8 { colour::linear_to_srgb_u8(colour.x) }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rg8;
+
+impl TexelFormat for Rg8 {
+    type Texel = [u8; 2];
+    const CHANNELS: usize = 2;
+    const INITIAL: [u8; 2] = [0; 2];
+
+    fn to_vec4(texel: [u8; 2]) -> glam::Vec4 {
+        glam::Vec4::new(colour::u8_to_f32(texel[0]), colour::u8_to_f32(texel[1]), 0.0, 1.0)
+    }
+
+    fn from_vec4(colour: glam::Vec4) -> [u8; 2] {
+        [colour::f32_to_u8(colour.x), colour::f32_to_u8(colour.y)]
+    }
+
+    fn srgb_to_vec4(texel: [u8; 2]) -> glam::Vec4 {
+        glam::Vec4::new(colour::srgb_u8_to_linear(texel[0]), colour::srgb_u8_to_linear(texel[1]), 0.0, 1.0)
+    }
+
+    fn srgb_from_vec4(colour: glam::Vec4) -> [u8; 2] {
+        [colour::linear_to_srgb_u8(colour.x), colour::linear_to_srgb_u8(colour.y)]
+    }
+}
+
+//Bytes in memory order, as images are usually stored
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rgba8;
+
+impl TexelFormat for Rgba8 {
+    type Texel = [u8; 4];
+    const CHANNELS: usize = 4;
+    const INITIAL: [u8; 4] = [0; 4];
+
+    fn to_vec4(texel: [u8; 4]) -> glam::Vec4 {
+        glam::Vec4::from_array(texel.map(colour::u8_to_f32))
+    }
+
+    fn from_vec4(colour: glam::Vec4) -> [u8; 4] {
+        colour.to_array().map(colour::f32_to_u8)
+    }
+
+    fn srgb_to_vec4(texel: [u8; 4]) -> glam::Vec4 {
+        let [r, g, b, a] = texel;
+        glam::Vec4::new(colour::srgb_u8_to_linear(r), colour::srgb_u8_to_linear(g), colour::srgb_u8_to_linear(b), colour::u8_to_f32(a))
+    }
+
+    fn srgb_from_vec4(colour: glam::Vec4) -> [u8; 4] {
+        [colour::linear_to_srgb_u8(colour.x), colour::linear_to_srgb_u8(colour.y), colour::linear_to_srgb_u8(colour.z), colour::f32_to_u8(colour.w)]
+    }
+}
+
+//Single float channel, e.g. height maps
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct R32F;
+
+impl TexelFormat for R32F {
+    type Texel = f32;
+    const CHANNELS: usize = 1;
+    const INITIAL: f32 = 0.0;
+
+    fn to_vec4(texel: f32) -> glam::Vec4 { glam::Vec4::new(texel, 0.0, 0.0, 1.0) }
+    fn from_vec4(colour: glam::Vec4) -> f32 { colour.x }
+}
+
+//Depth is an R32F that starts at the far plane
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct D32F;
+
+impl TexelFormat for D32F {
+    type Texel = f32;
+    const CHANNELS: usize = 1;
+    const INITIAL: f32 = 1.0;
+
+    fn to_vec4(texel: f32) -> glam::Vec4 { glam::Vec4::new(texel, 0.0, 0.0, 1.0) }
+    fn from_vec4(colour: glam::Vec4) -> f32 { colour.x }
+}
+
+//Unclamped, for HDR images and render targets
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rgba32F;
+
+impl TexelFormat for Rgba32F {
+    type Texel = glam::Vec4;
+    const CHANNELS: usize = 4;
+    const INITIAL: glam::Vec4 = glam::Vec4::ZERO;
+
+    fn to_vec4(texel: glam::Vec4) -> glam::Vec4 { texel }
+    fn from_vec4(colour: glam::Vec4) -> glam::Vec4 { colour }
+}
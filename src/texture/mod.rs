@@ -1,14 +1,25 @@
 use std::path::Path;
 use stb_image::image;
 use crate::math;
+use crate::math::colour::ColourSpace;
 
-#[derive(Default)]
-pub struct Texture {
-    data: Vec<u32>,
+pub mod export;
+pub mod font;
+pub mod format;
+
+pub use format::TexelFormat;
+
+pub struct Texture<F: TexelFormat = format::Argb8> {
+    data: Vec<F::Texel>,
     width: usize,
-    height: usize
+    height: usize,
+    //How the colour channels are encoded, alpha is always linear
+    colour_space: ColourSpace
 }
 
+pub type FloatTexture = Texture<format::Rgba32F>;
+pub type DepthTexture = Texture<format::D32F>;
+
 pub fn load_image_file(path: &Path) -> Result<Texture, String> {
     let decoded_image = image::load(path);
 
@@ -16,8 +27,31 @@ pub fn load_image_file(path: &Path) -> Result<Texture, String> {
         image::LoadResult::ImageU8(image) => {
             Ok(load_image_memory(&image))
         }
+        image::LoadResult::ImageF32(_) => {
+            Err("Float images need load_float_image_file".to_string())
+        }
+        image::LoadResult::Error(msg) => {
+            Err(msg)
+        }
+    }
+}
+
+//Loads HDR (Radiance .hdr) images as is, LDR images are decoded from sRGB to linear
+pub fn load_float_image_file(path: &Path) -> Result<FloatTexture, String> {
+    let decoded_image = image::load(path);
+
+    match decoded_image {
+        image::LoadResult::ImageU8(image) => {
+            //Alpha is the last channel of 2 and 4 channel images
+            let has_alpha = image.depth == 2 || image.depth == 4;
+            let data = image.data.iter().enumerate().map(|(i, v)| {
+                if has_alpha && i % image.depth == image.depth - 1 { math::colour::u8_to_f32(*v) }
+                else { math::colour::srgb_u8_to_linear(*v) }
+            }).collect::<Vec<f32>>();
+            Ok(load_float_image_memory(&data, image.width, image.height, image.depth))
+        }
         image::LoadResult::ImageF32(image) => {
-            Err("Float images not supported".to_string())
+            Ok(load_float_image_memory(&image.data, image.width, image.height, image.depth))
         }
         image::LoadResult::Error(msg) => {
             Err(msg)
@@ -25,6 +59,20 @@ pub fn load_image_file(path: &Path) -> Result<Texture, String> {
     }
 }
 
+fn load_float_image_memory(data: &[f32], width: usize, height: usize, channels: usize) -> FloatTexture {
+
+    let out_data = data.chunks_exact(channels).map(|texel| {
+        match channels {
+            1 => glam::Vec4::new(texel[0], texel[0], texel[0], 1.0),
+            2 => glam::Vec4::new(texel[0], texel[0], texel[0], texel[1]),
+            3 => glam::Vec4::new(texel[0], texel[1], texel[2], 1.0),
+            _ => glam::Vec4::new(texel[0], texel[1], texel[2], texel[3])
+        }
+    }).collect();
+
+    FloatTexture::from_data(out_data, width, height)
+}
+
 fn load_image_memory(image: &image::Image<u8>) -> Texture {
 
     let channels = image.depth;
@@ -56,70 +104,150 @@ fn load_image_memory(image: &image::Image<u8>) -> Texture {
         }
     }
 
-    Texture::from_data(out_data, image.width, image.height)
+    //Colour images are authored in sRGB
+    Texture::from_data(out_data, image.width, image.height).with_colour_space(ColourSpace::Srgb)
 }
 
-//ARGB texture
-impl Texture {
+impl<F: TexelFormat> Default for Texture<F> {
+    fn default() -> Self {
+        Self::from_data(Vec::new(), 0, 0)
+    }
+}
+
+impl<F: TexelFormat> Texture<F> {
     pub fn new(width: usize, height: usize) -> Self {
-        Self { data: vec![0; width * height], width, height}
+        Self::from_data(vec![F::INITIAL; width * height], width, height)
     }
 
-    pub fn from_data(data: Vec<u32>, width: usize, height: usize) -> Self {
+    pub fn from_data(data: Vec<F::Texel>, width: usize, height: usize) -> Self {
         debug_assert!(width * height == data.len());
-        Self { data, width, height}
+        Self { data, width, height, colour_space: ColourSpace::Linear }
     }
 
-    pub fn width(&self) -> usize { self.width }
-    pub fn height(&self) -> usize { self.height }
-
-    //RGB
-    pub fn read(&self, i: usize, j: usize) -> u32 {
-        let index = self.width * j + i;
-        self.data[index]
+    //1x1 texture, handy as a default binding
+    pub fn solid(texel: F::Texel) -> Self {
+        Self::from_data(vec![texel], 1, 1)
     }
 
-    pub fn write(&mut self, i: usize, j: usize, colour: u32) {
-        let index = self.width * j + i;
-        self.data[index] = colour;
+    pub fn with_colour_space(mut self, colour_space: ColourSpace) -> Self {
+        self.colour_space = colour_space;
+        self
     }
 
-    pub fn clear(&mut self, val: u32) {
-        self.data.fill(val)
-    }
+    pub fn colour_space(&self) -> ColourSpace { self.colour_space }
+    pub fn set_colour_space(&mut self, colour_space: ColourSpace) { self.colour_space = colour_space; }
 
-    pub fn as_slice(&self) -> &[u32] { &self.data }
-}
+    //Linear RGBA, decoding sRGB
+    pub fn read_linear(&self, i: usize, j: usize) -> glam::Vec4 {
+        match self.colour_space {
+            ColourSpace::Linear => F::to_vec4(self.read(i, j)),
+            ColourSpace::Srgb => F::srgb_to_vec4(self.read(i, j))
+        }
+    }
 
-pub struct DepthTexture {
-    data: Vec<f32>,
-    width: usize,
-    height: usize
-}
+    //Linear RGBA, encoded to sRGB if needed
+    pub fn write_linear(&mut self, i: usize, j: usize, colour: glam::Vec4) {
+        let texel = self.encode(colour);
+        self.write(i, j, texel);
+    }
 
-impl DepthTexture {
-    pub fn new(width: usize, height: usize) -> Self {
-        Self { data: vec![1.0; width * height], width, height}
+    fn encode(&self, colour: glam::Vec4) -> F::Texel {
+        match self.colour_space {
+            ColourSpace::Linear => F::from_vec4(colour),
+            ColourSpace::Srgb => F::srgb_from_vec4(colour)
+        }
     }
 
     pub fn width(&self) -> usize { self.width }
     pub fn height(&self) -> usize { self.height }
 
-    //RGB
-    pub fn read(&self, i: usize, j: usize) -> f32 {
+    pub fn read(&self, i: usize, j: usize) -> F::Texel {
         let index = self.width * j + i;
         self.data[index]
     }
 
-    pub fn write(&mut self, i: usize, j: usize, val: f32) {
+    pub fn write(&mut self, i: usize, j: usize, texel: F::Texel) {
         let index = self.width * j + i;
-        self.data[index] = val;
+        self.data[index] = texel;
     }
 
-    pub fn clear(&mut self, val: f32) {
+    pub fn clear(&mut self, val: F::Texel) {
         self.data.fill(val)
     }
 
+    pub fn as_slice(&self) -> &[F::Texel] { &self.data }
+}
+
+impl Texture {
+    //Size in pixels of text drawn with draw_text
+    pub fn measure_text(text: &str) -> (usize, usize) {
+        let columns = text.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+        let rows = text.lines().count();
+        (columns * font::GLYPH_WIDTH, rows * font::GLYPH_HEIGHT)
+    }
+
+    //Draws with the built in font, x and y are the top left corner.
+    //Newlines start a new row, characters outside printable ASCII show as '?'
+    pub fn draw_text(&mut self, x: usize, y: usize, text: &str, colour: u32) {
+
+        for (row, line) in text.lines().enumerate() {
+            for (column, character) in line.chars().enumerate() {
+
+                let character = if (font::FIRST_CHAR..=font::LAST_CHAR).contains(&character) { character } else { '?' };
+                let glyph = &font::GLYPHS[character as usize - font::FIRST_CHAR as usize];
+
+                let glyph_x = x + column * font::GLYPH_WIDTH;
+                let glyph_y = y + row * font::GLYPH_HEIGHT;
+
+                for (j, bits) in glyph.iter().enumerate() {
+                    for i in 0..font::GLYPH_WIDTH {
+
+                        let lit = bits & (1 << (font::GLYPH_WIDTH - 1 - i)) != 0;
+                        let (px, py) = (glyph_x + i, glyph_y + j);
+
+                        if lit && px < self.width && py < self.height {
+                            self.write(px, py, colour);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+//Colour attachment the shaders can write to, colours are RGBA
+pub trait RenderTarget {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    fn read_colour(&self, i: usize, j: usize) -> glam::Vec4;
+    fn write_colour(&mut self, i: usize, j: usize, colour: glam::Vec4);
+    fn clear_colour(&mut self, colour: glam::Vec4);
+}
+
+//Anything a Sampler can read from, colours are RGBA
+pub trait SampleSource {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    fn read_colour(&self, i: usize, j: usize) -> glam::Vec4;
+}
+
+//Shaders work in linear space, sRGB textures are decoded on read and
+//encoded on write. 8 bit formats clamp to 0-1 on write, float formats don't.
+impl<F: TexelFormat> RenderTarget for Texture<F> {
+    fn width(&self) -> usize { self.width }
+    fn height(&self) -> usize { self.height }
+    fn read_colour(&self, i: usize, j: usize) -> glam::Vec4 { self.read_linear(i, j) }
+    fn write_colour(&mut self, i: usize, j: usize, colour: glam::Vec4) { self.write_linear(i, j, colour) }
+    fn clear_colour(&mut self, colour: glam::Vec4) { self.clear(self.encode(colour)) }
+}
+
+impl<F: TexelFormat> SampleSource for Texture<F> {
+    fn width(&self) -> usize { self.width }
+    fn height(&self) -> usize { self.height }
+    fn read_colour(&self, i: usize, j: usize) -> glam::Vec4 { self.read_linear(i, j) }
+}
+
+impl DepthTexture {
     //replaces and returns true if input < previous value
     pub fn depth_test(&mut self, i: usize, j: usize, depth_val: f32) -> bool {
         if depth_val < self.read(i, j) { self.write(i, j, depth_val); true}
@@ -127,15 +255,81 @@ impl DepthTexture {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub struct Sampler {
 
 }
 
 impl Sampler {
-    pub fn sample(&self, texture: &Texture, uv: glam::Vec2) -> glam::Vec4 {
+    pub fn sample<T: SampleSource>(&self, texture: &T, uv: glam::Vec2) -> glam::Vec4 {
         let dimensions = ((texture.width() - 1) as f32, (texture.height() - 1) as f32);
         let (i, j) = (uv.x * dimensions.0, (1.0 - uv.y) * dimensions.1);
-        math::colour::hex_to_f32(texture.read(i.round() as usize, j.round() as usize))
+        texture.read_colour(i.round() as usize, j.round() as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format, load_float_image_file, DepthTexture, RenderTarget, Sampler, Texture};
+    use crate::math::colour::ColourSpace;
+
+    #[test]
+    fn text_is_drawn_and_clipped() {
+        let mut texture = Texture::new(8, 8);
+        texture.draw_text(0, 0, "A", 0xFFFFFFFF);
+        texture.draw_text(6, 6, "W", 0xFFFFFFFF);
+
+        //Top of the A, a lone pixel on the second row
+        assert_eq!(texture.read(2, 1), 0xFFFFFFFF);
+        assert_eq!(texture.read(1, 1), 0);
+        assert_eq!(Texture::measure_text("ab\ncde"), (18, 20));
+    }
+
+    #[test]
+    fn load_radiance_hdr() {
+        //Two flat RGBE pixels, 4.0 and 0.5 in every channel
+        let mut file = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n".to_vec();
+        file.extend_from_slice(&[128, 128, 128, 131, 128, 128, 128, 128]);
+
+        let path = std::env::temp_dir().join(format!("rusterizer_load_radiance_hdr_{}.hdr", std::process::id()));
+        std::fs::write(&path, file).unwrap();
+
+        let texture = load_float_image_file(&path).unwrap();
+        assert_eq!((texture.width(), texture.height()), (2, 1));
+        assert!(texture.read(0, 0).abs_diff_eq(glam::Vec4::new(4.0, 4.0, 4.0, 1.0), 1e-6));
+        assert!(texture.read(1, 0).abs_diff_eq(glam::Vec4::new(0.5, 0.5, 0.5, 1.0), 1e-6));
+    }
+
+    #[test]
+    fn srgb_decode_on_sample_and_encode_on_write() {
+        //50% grey in sRGB is about 21% in linear
+        let texture: Texture = Texture::from_data(vec![0xFF808080], 1, 1).with_colour_space(ColourSpace::Srgb);
+        let sampled = Sampler::default().sample(&texture, glam::Vec2::ZERO);
+        assert!((sampled.x - 0.2158605).abs() < 1e-4 && sampled.w == 1.0);
+
+        let mut target: Texture = Texture::new(1, 1).with_colour_space(ColourSpace::Srgb);
+        target.write_colour(0, 0, sampled);
+        assert_eq!(target.read(0, 0), 0xFF808080);
+    }
+
+    #[test]
+    fn texel_formats_share_read_write_and_sampling() {
+        let mut mask = Texture::<format::R8>::new(2, 1);
+        mask.write_colour(1, 0, glam::Vec4::new(2.0, 0.5, 0.5, 0.5));
+        assert_eq!(mask.as_slice(), &[0, 255]);
+        assert_eq!(Sampler::default().sample(&mask, glam::Vec2::ONE), glam::Vec4::new(1.0, 0.0, 0.0, 1.0));
+
+        let mut rgba = Texture::<format::Rgba8>::new(1, 1).with_colour_space(ColourSpace::Srgb);
+        rgba.clear_colour(glam::Vec4::new(0.2158605, 1.0, 0.0, 0.5));
+        assert_eq!(rgba.read(0, 0), [128, 255, 0, 127]);
+
+        let mut heights = Texture::<format::R32F>::new(1, 1);
+        heights.write_colour(0, 0, glam::Vec4::splat(-3.5));
+        assert_eq!(heights.read(0, 0), -3.5);
+
+        //Depth starts cleared to the far plane
+        let mut depth = DepthTexture::new(1, 1);
+        assert_eq!(depth.read(0, 0), 1.0);
+        assert!(depth.depth_test(0, 0, 0.5) && !depth.depth_test(0, 0, 0.75));
     }
-}
\ No newline at end of file
+}
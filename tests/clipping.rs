@@ -0,0 +1,207 @@
+//Randomized checks for the clipping and rasterization maths
+
+use glam::{Vec2, Vec3, Vec4};
+use proptest::prelude::*;
+
+use rusterizer_s::math;
+use rusterizer_s::math::bounding_box::{BoundingBox, Line};
+
+const EPSILON: f32 = 1e-4;
+
+fn clip_vertex() -> impl Strategy<Value = Vec4> {
+    (-4.0f32..4.0, -4.0f32..4.0, -4.0f32..4.0, -2.0f32..4.0).prop_map(|(x, y, z, w)| Vec4::new(x, y, z, w))
+}
+
+fn screen_point(range: f32) -> impl Strategy<Value = Vec2> {
+    (-range..range, -range..range).prop_map(|(x, y)| Vec2::new(x, y))
+}
+
+//Half pixel coordinates put pixel centers exactly on edges
+fn snapped_or_free_point(range: f32) -> impl Strategy<Value = Vec2> {
+    let snapped = (-(range as i32) * 2..(range as i32) * 2, -(range as i32) * 2..(range as i32) * 2)
+        .prop_map(|(x, y)| Vec2::new(x as f32, y as f32) * 0.5);
+    prop_oneof![screen_point(range), snapped]
+}
+
+fn polygon_area(points: &[Vec2]) -> f32 {
+    let twice_area: f32 = (0..points.len()).map(|i| points[i].perp_dot(points[(i + 1) % points.len()])).sum();
+    twice_area.abs() * 0.5
+}
+
+//Clips a convex polygon by the half plane to the left of a to b, as f64 to act as the reference
+fn clip_by_edge(polygon: &[[f64; 2]], a: [f64; 2], b: [f64; 2]) -> Vec<[f64; 2]> {
+    let side = |p: [f64; 2]| (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0]);
+    let mut out = Vec::new();
+
+    for i in 0..polygon.len() {
+        let (current, next) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+        let (current_side, next_side) = (side(current), side(next));
+
+        if current_side >= 0.0 { out.push(current); }
+        if (current_side >= 0.0) != (next_side >= 0.0) {
+            let t = current_side / (current_side - next_side);
+            out.push([current[0] + (next[0] - current[0]) * t, current[1] + (next[1] - current[1]) * t]);
+        }
+    }
+    out
+}
+
+fn inside_triangle_f64(p: [f64; 2], a: [f64; 2], b: [f64; 2], c: [f64; 2]) -> bool {
+    let edge = |a: [f64; 2], b: [f64; 2]| (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0]);
+    let (e1, e2, e3) = (edge(a, b), edge(b, c), edge(c, a));
+    (e1 >= 0.0 && e2 >= 0.0 && e3 >= 0.0) || (e1 <= 0.0 && e2 <= 0.0 && e3 <= 0.0)
+}
+
+fn distance_to_segment_f64(p: [f64; 2], a: [f64; 2], b: [f64; 2]) -> f64 {
+    let (ab, ap) = ([b[0] - a[0], b[1] - a[1]], [p[0] - a[0], p[1] - a[1]]);
+    let t = ((ap[0] * ab[0] + ap[1] * ab[1]) / (ab[0] * ab[0] + ab[1] * ab[1])).clamp(0.0, 1.0);
+    let (dx, dy) = (ap[0] - ab[0] * t, ap[1] - ab[1] * t);
+    (dx * dx + dy * dy).sqrt()
+}
+
+fn as_f64(v: Vec2) -> [f64; 2] { [v.x as f64, v.y as f64] }
+
+proptest! {
+    #[test]
+    fn clipped_triangles_lie_inside_every_plane(a in clip_vertex(), b in clip_vertex(), c in clip_vertex()) {
+        for (vertex, _) in math::clip_homogenous_triangle(&[a, b, c]) {
+            for plane in math::CLIP_PLANES {
+                let scale = vertex.abs().max_element().max(1.0);
+                prop_assert!(plane.dot(vertex) >= -EPSILON * scale, "{:?} outside {:?}", vertex, plane);
+            }
+        }
+    }
+
+    #[test]
+    fn guard_band_clipping_only_cuts_outside_the_band(a in clip_vertex(), b in clip_vertex(), c in clip_vertex()) {
+        let clipped = math::clip_guard_band_triangle(&[a, b, c]);
+        for (vertex, _) in &clipped {
+            for plane in math::GUARD_BAND_PLANES {
+                let scale = vertex.abs().max_element().max(1.0);
+                prop_assert!(plane.dot(*vertex) >= -EPSILON * scale, "{:?} outside {:?}", vertex, plane);
+            }
+        }
+
+        if math::triangle_in_guard_band(a, b, c) {
+            let vertices: Vec<Vec4> = clipped.iter().map(|(v, _)| *v).collect();
+            prop_assert_eq!(vertices, vec![a, b, c]);
+        }
+    }
+
+    #[test]
+    fn clipped_barycentrics_sum_to_one_and_rebuild_the_vertex(a in clip_vertex(), b in clip_vertex(), c in clip_vertex()) {
+        for (vertex, weights) in math::clip_homogenous_triangle(&[a, b, c]) {
+            prop_assert!((weights.x + weights.y + weights.z - 1.0).abs() < EPSILON);
+            prop_assert!(weights.min_element() >= -EPSILON);
+
+            let rebuilt = math::barycentric_lerp(weights, a, b, c);
+            prop_assert!(rebuilt.abs_diff_eq(vertex, EPSILON * 4.0 * vertex.abs().max_element().max(1.0)));
+        }
+    }
+
+    //Inside the clip volume on z, so only the x and y planes cut
+    #[test]
+    fn clipped_area_matches_the_square_clipped_by_the_triangle(a in screen_point(3.0), b in screen_point(3.0), c in screen_point(3.0)) {
+        let clipped = math::clip_homogenous_triangle(&[a.extend(0.5).extend(1.0), b.extend(0.5).extend(1.0), c.extend(0.5).extend(1.0)]);
+        let points: Vec<Vec2> = clipped.iter().map(|(v, _)| v.truncate().truncate() / v.w).collect();
+        let area = if points.len() < 3 { 0.0 } else { polygon_area(&points) };
+
+        //Counter clockwise so the inside of every edge is on the left
+        let (a, b, c) = (as_f64(a), as_f64(b), as_f64(c));
+        let (b, c) = if (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]) < 0.0 { (c, b) } else { (b, c) };
+
+        let mut reference = vec![[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]];
+        for (start, end) in [(a, b), (b, c), (c, a)] {
+            reference = clip_by_edge(&reference, start, end);
+        }
+        let reference: Vec<Vec2> = reference.iter().map(|p| Vec2::new(p[0] as f32, p[1] as f32)).collect();
+        let reference_area = if reference.len() < 3 { 0.0 } else { polygon_area(&reference) };
+
+        prop_assert!((area - reference_area).abs() < 1e-3, "{} vs {}", area, reference_area);
+    }
+
+    #[test]
+    fn barycentric_weights_sum_to_one(p in screen_point(64.0), a in screen_point(64.0), b in screen_point(64.0), c in screen_point(64.0)) {
+        prop_assume!(math::edge_function(a, b, c).abs() > 1.0);
+        if let Some(weights) = math::barycentric_weights(p, a, b, c) {
+            prop_assert!((weights.x + weights.y + weights.z - 1.0).abs() < EPSILON);
+            prop_assert!(math::barycentric_lerp(weights, a, b, c).abs_diff_eq(p, 1e-2));
+        }
+    }
+
+    //Every pixel center in the quad abcd, split along ac, must hit at least one of the triangles
+    #[test]
+    fn triangles_sharing_an_edge_leave_no_gaps(a in snapped_or_free_point(16.0), b in snapped_or_free_point(16.0), c in snapped_or_free_point(16.0), d in snapped_or_free_point(16.0)) {
+        prop_assume!(math::edge_function(a, b, c).abs() > 1.0 && math::edge_function(a, c, d).abs() > 1.0);
+
+        let (fa, fb, fc, fd) = (as_f64(a), as_f64(b), as_f64(c), as_f64(d));
+        let outer_edges = [(fa, fb), (fb, fc), (fc, fd), (fd, fa)];
+
+        for j in -16..16 {
+            for i in -16..16 {
+                let pixel = Vec2::new(i as f32 + 0.5, j as f32 + 0.5);
+                let p = as_f64(pixel);
+
+                let in_union = inside_triangle_f64(p, fa, fb, fc) || inside_triangle_f64(p, fa, fc, fd);
+                let near_outside = outer_edges.iter().any(|(s, e)| distance_to_segment_f64(p, *s, *e) < 1e-3);
+                if !in_union || near_outside { continue; }
+
+                let covered = math::barycentric_weights(pixel, a, b, c).is_some() || math::barycentric_weights(pixel, a, c, d).is_some();
+                prop_assert!(covered, "gap at {:?}", pixel);
+            }
+        }
+    }
+
+    #[test]
+    fn line_intersection_lies_on_both_lines(p in screen_point(100.0), angle in 0.0f32..std::f32::consts::TAU, between in 0.1f32..3.0, s in 0.05f32..0.95, t in 0.05f32..0.95, scale in 10.0f32..100.0) {
+        let (d1, d2) = (Vec2::from_angle(angle), Vec2::from_angle(angle + between));
+
+        //Both segments pass through p, at s along the first and t along the second
+        let first = Line::new(p - d1 * scale * s, p + d1 * scale * (1.0 - s));
+        let second = Line::new(p - d2 * scale * t, p + d2 * scale * (1.0 - t));
+
+        let u = first.intersect(&second);
+        prop_assert!(u.is_some());
+        let u = u.unwrap();
+        prop_assert!((u - t).abs() < 1e-3, "{} vs {}", u, t);
+        prop_assert!(math::lerp(second.start, second.end, u).abs_diff_eq(p, 1e-2));
+    }
+
+    #[test]
+    fn clipped_lines_stay_in_the_box(start in screen_point(1000.0), end in screen_point(1000.0), min in (0u32..300, 0u32..300), size in (1u32..300, 1u32..300)) {
+        let bounds = BoundingBox::new(glam::UVec2::new(min.0, min.1), glam::UVec2::new(min.0 + size.0, min.1 + size.1));
+        let (low, high) = (bounds.start.as_vec2() - 1e-2, bounds.end.as_vec2() + 1e-2);
+        let inside = |p: Vec2| p.cmpge(low).all() && p.cmple(high).all();
+
+        let line = Line::new(start, end);
+        match bounds.clip_line(&line) {
+            Some(clipped) => {
+                prop_assert!(inside(clipped.start) && inside(clipped.end), "{:?} escapes {:?}", clipped, bounds);
+                //Still part of the original line
+                prop_assert!(math::edge_function(clipped.start, start, end).abs() <= (end - start).length() * 1e-2);
+                prop_assert!(math::edge_function(clipped.end, start, end).abs() <= (end - start).length() * 1e-2);
+            }
+            None => {
+                //Nothing along the line is inside
+                for k in 0..=64 {
+                    let sample = math::lerp(start, end, k as f32 / 64.0);
+                    let shrunk = bounds.start.as_vec2() + 1e-1..bounds.end.as_vec2() - 1e-1;
+                    prop_assert!(!(sample.cmpgt(shrunk.start).all() && sample.cmplt(shrunk.end).all()), "{:?} was rejected", line);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn vertices_on_a_plane_are_not_duplicated() {
+    //The second vertex sits exactly on the left plane
+    let triangle = [Vec4::new(-2.0, 0.0, 0.5, 1.0), Vec4::new(-1.0, 0.5, 0.5, 1.0), Vec4::new(0.5, -0.5, 0.5, 1.0)];
+    let clipped = math::clip_homogenous_triangle(&triangle);
+
+    for (i, (vertex, _)) in clipped.iter().enumerate() {
+        let next = clipped[(i + 1) % clipped.len()].0;
+        assert!(!vertex.abs_diff_eq(next, EPSILON), "{:?}", clipped);
+    }
+    assert!(clipped.iter().all(|(_, weights)| (weights.dot(Vec3::ONE) - 1.0).abs() < EPSILON));
+}
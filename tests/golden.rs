@@ -0,0 +1,364 @@
+//Renders reference scenes headlessly and compares them to the images in tests/goldens.
+//Run with RUSTERIZER_BLESS=1 to overwrite the goldens with the current output.
+//On failure the actual and diff images are written to the cargo target tmp dir.
+
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
+
+use rusterizer_s::camera::Camera;
+use rusterizer_s::math::colour::ColourSpace;
+use rusterizer_s::renderer::command::{CommandBuffer, PipelineState};
+use rusterizer_s::renderer::data::{InstanceData, Mesh, Topology, VertexInput};
+use rusterizer_s::renderer::hiz::HiZBuffer;
+use rusterizer_s::renderer::{debug, debug_draw, state, tonemap, RenderTargets};
+use rusterizer_s::scene::{Material, Scene, Transform};
+use rusterizer_s::texture::{format, load_image_file, DepthTexture, FloatTexture, RenderTarget, Sampler, Texture};
+
+const WIDTH: usize = 96;
+const HEIGHT: usize = 72;
+const BLESS_VAR: &str = "RUSTERIZER_BLESS";
+
+#[derive(Debug, Clone, Copy)]
+struct Tolerance {
+    //Largest difference in any 8 bit channel that still counts as equal
+    per_channel: u8,
+    max_differing_pixels: usize
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self { per_channel: 2, max_differing_pixels: 4 }
+    }
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("goldens").join(format!("{}.png", name))
+}
+
+fn output_path(name: &str, suffix: &str) -> PathBuf {
+    let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    std::fs::create_dir_all(&directory).unwrap();
+    directory.join(format!("{}_{}.png", name, suffix))
+}
+
+fn check_golden(name: &str, actual: &Texture, tolerance: Tolerance) {
+
+    let path = golden_path(name);
+
+    if std::env::var_os(BLESS_VAR).is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        actual.save(&path).unwrap();
+        return;
+    }
+
+    let expected = load_image_file(&path)
+        .unwrap_or_else(|error| panic!("No golden for {} ({}), run with {}=1 to create it", name, error, BLESS_VAR));
+
+    assert_eq!((expected.width(), expected.height()), (actual.width(), actual.height()), "{}: size differs from the golden", name);
+
+    let expected_rgba = expected.to_rgba8();
+    let actual_rgba = actual.to_rgba8();
+
+    //Differing pixels are red, matching ones a faded copy of the actual image
+    let mut differing = 0;
+    let diff = expected_rgba.chunks_exact(4).zip(actual_rgba.chunks_exact(4)).map(|(e, a)| {
+        let largest = e.iter().zip(a).map(|(e, a)| e.abs_diff(*a)).max().unwrap();
+        if largest > tolerance.per_channel {
+            differing += 1;
+            [255, 0, 0, 255]
+        } else {
+            [a[0] / 4, a[1] / 4, a[2] / 4, 255]
+        }
+    }).collect();
+
+    if differing > tolerance.max_differing_pixels {
+        let actual_path = output_path(name, "actual");
+        let diff_path = output_path(name, "diff");
+        actual.save(&actual_path).unwrap();
+        Texture::<format::Rgba8>::from_data(diff, actual.width(), actual.height()).save(&diff_path).unwrap();
+
+        panic!(
+            "{}: {} pixels differ by more than {}, {} allowed. Wrote {} and {}",
+            name, differing, tolerance.per_channel, tolerance.max_differing_pixels, actual_path.display(), diff_path.display()
+        );
+    }
+}
+
+fn camera() -> Camera {
+    Camera {
+        position: Vec3::new(0.8, 0.9, 1.6),
+        euler_rotation: Vec3::new(0.45, -0.45, 0.0),
+        aspect_ratio: WIDTH as f32 / HEIGHT as f32,
+        fov: std::f32::consts::PI * 0.3,
+        near: 0.1,
+        far: 10.0
+    }
+}
+
+fn quad() -> Mesh {
+    let uvs = vec![Vec2::new(0.0, 1.0), Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), Vec2::new(1.0, 1.0)];
+    let vertices = VertexInput {
+        positions: vec![Vec3::new(-0.5, 0.5, 0.5), Vec3::new(-0.5, -0.5, 0.5), Vec3::new(0.5, -0.5, 0.5), Vec3::new(0.5, 0.5, 0.5)],
+        colours: uvs.iter().map(|uv| Vec3::new(uv.x, uv.y, 1.0)).collect(),
+        uvs,
+        ..Default::default()
+    };
+    Mesh::new(vertices, vec![0u16, 1, 2, 2, 3, 0])
+}
+
+fn checker() -> Texture {
+    let data = (0..64).map(|i| if (i % 8 + i / 8) % 2 == 0 { 0xFFFFFFFF } else { 0xFF404040 }).collect();
+    Texture::from_data(data, 8, 8).with_colour_space(ColourSpace::Srgb)
+}
+
+fn surfaces() -> (Texture, DepthTexture) {
+    (Texture::new(WIDTH, HEIGHT).with_colour_space(ColourSpace::Srgb), DepthTexture::new(WIDTH, HEIGHT))
+}
+
+#[test]
+fn textured_cube() {
+    let mut scene = Scene::default();
+    let quad = scene.add_mesh(quad());
+    let material = scene.add_material(Material { texture: Rc::new(checker()), sampler: Sampler::default() });
+
+    let cube = scene.add_node("cube", None, Transform::default());
+    let rotations = [
+        Quat::IDENTITY,
+        Quat::from_rotation_y(std::f32::consts::PI * 0.5),
+        Quat::from_rotation_y(std::f32::consts::PI),
+        Quat::from_rotation_y(std::f32::consts::PI * 1.5),
+        Quat::from_rotation_x(std::f32::consts::PI * 0.5),
+        Quat::from_rotation_x(std::f32::consts::PI * -0.5)
+    ];
+    for rotation in rotations {
+        let face = scene.add_node("face", Some(cube), Transform::from_rotation(rotation));
+        scene.set_mesh(face, quad, Some(material));
+    }
+
+    let (mut colour, mut depth) = surfaces();
+    colour.clear_colour(Vec4::new(0.0, 0.0, 0.0, 1.0));
+    rusterizer_s::renderer::render(&mut scene, &camera(), &mut RenderTargets::new(&mut colour, &mut depth));
+
+    check_golden("textured_cube", &colour, Tolerance::default());
+}
+
+#[test]
+fn blending_and_wireframe() {
+    let (view, projection) = camera().generate_view_projection();
+    let quad = Rc::new(quad());
+
+    let solid_wireframe = PipelineState {
+        raster: state::RasterState { fill_mode: state::FillMode::SolidWireframe, ..Default::default() },
+        ..Default::default()
+    };
+    let alpha = PipelineState {
+        blend: state::BlendState { mode: state::BlendMode::Alpha },
+        depth: state::DepthState { write: false, ..Default::default() },
+        ..Default::default()
+    };
+
+    let mut commands = CommandBuffer::default();
+    commands.clear(Some(Vec4::new(0.1, 0.1, 0.2, 1.0)), Some(1.0));
+    commands.set_uniforms(view, projection);
+    commands.bind_pipeline(Rc::new(solid_wireframe));
+    commands.draw_indexed(quad.clone(), Mat4::IDENTITY);
+    commands.bind_pipeline(Rc::new(alpha));
+    commands.draw_indexed_instanced(quad, vec![InstanceData {
+        model: Mat4::from_translation(Vec3::new(0.3, 0.2, 0.3)),
+        tint: Vec4::new(1.0, 0.3, 0.2, 0.5),
+        ..Default::default()
+    }]);
+
+    let (mut colour, mut depth) = surfaces();
+    commands.submit(&mut RenderTargets::new(&mut colour, &mut depth));
+
+    check_golden("blending_and_wireframe", &colour, Tolerance::default());
+}
+
+#[test]
+fn points_and_debug_lines() {
+    let camera = camera();
+    let (view, projection) = camera.generate_view_projection();
+
+    let positions: Vec<Vec3> = (0..24).map(|i| {
+        let angle = i as f32 / 24.0 * std::f32::consts::TAU;
+        Vec3::new(angle.cos() * 0.8, 0.0, angle.sin() * 0.8)
+    }).collect();
+    let vertices = VertexInput {
+        colours: positions.iter().map(|p| *p * 0.5 + 0.5).collect(),
+        uvs: vec![Vec2::ZERO; positions.len()],
+        positions,
+        ..Default::default()
+    };
+    let indices: Vec<usize> = (0..vertices.positions.len()).collect();
+    let ring = Rc::new(Mesh::with_topology(vertices, indices, Topology::PointList));
+
+    let points = PipelineState {
+        point: state::PointState { size: 4.0, shape: state::PointShape::Round, sprite: false },
+        ..Default::default()
+    };
+
+    let mut commands = CommandBuffer::default();
+    commands.clear(Some(Vec4::new(0.0, 0.0, 0.0, 1.0)), Some(1.0));
+    commands.set_uniforms(view, projection);
+    commands.bind_pipeline(Rc::new(points));
+    commands.draw_indexed(ring, Mat4::IDENTITY);
+
+    let (mut colour, mut depth) = surfaces();
+    commands.submit(&mut RenderTargets::new(&mut colour, &mut depth));
+
+    let shader = debug::DebugLineShader { camera, mode: debug::LineMode::AntiAliased, ..Default::default() };
+    let mut lines = debug_draw::DebugDraw::default();
+    lines.grid(Vec3::new(0.0, -0.5, 0.0), 0.5, 4, Vec4::new(0.5, 0.5, 0.5, 1.0), 1);
+    lines.axes(&Mat4::IDENTITY, 0.5, 1);
+    lines.flush(&shader, &mut colour, Some(&depth));
+
+    check_golden("points_and_debug_lines", &colour, Tolerance::default());
+}
+
+#[test]
+fn hdr_tone_mapped() {
+    let (view, projection) = camera().generate_view_projection();
+    let quad = Rc::new(quad());
+
+    //One face well above 1.0, one below
+    let instances = vec![
+        InstanceData { tint: Vec4::new(6.0, 6.0, 6.0, 1.0), ..Default::default() },
+        InstanceData {
+            model: Mat4::from_rotation_y(std::f32::consts::PI * 0.5),
+            tint: Vec4::new(0.5, 0.5, 0.5, 1.0),
+            ..Default::default()
+        }
+    ];
+
+    let mut commands = CommandBuffer::default();
+    commands.clear(Some(Vec4::new(0.0, 0.0, 0.0, 1.0)), Some(1.0));
+    commands.set_uniforms(view, projection);
+    commands.draw_indexed_instanced(quad, instances);
+
+    let mut hdr = FloatTexture::new(WIDTH, HEIGHT);
+    let (mut colour, mut depth) = surfaces();
+    commands.submit(&mut RenderTargets::new(&mut hdr, &mut depth));
+
+    let mut tone_mapper = tonemap::ToneMapper::new(tonemap::ToneMapOperator::AcesFitted, tonemap::Exposure::Manual(0.0));
+    tone_mapper.resolve(&hdr, &mut colour, 0.0);
+
+    check_golden("hdr_tone_mapped", &colour, Tolerance::default());
+}
+
+//Crosses the screen edges and the guard band, the ground should reach every edge of the image
+#[test]
+fn large_ground_plane() {
+    let camera = Camera { position: Vec3::new(0.0, 0.5, 0.0), euler_rotation: Vec3::new(0.3, -0.2, 0.0), ..camera() };
+    let (view, projection) = camera.generate_view_projection();
+
+    let ground = Mat4::from_scale_rotation_translation(Vec3::splat(200.0), Quat::from_rotation_x(std::f32::consts::PI * -0.5), Vec3::new(0.0, -100.0, 0.0));
+
+    let mut commands = CommandBuffer::default();
+    commands.clear(Some(Vec4::new(0.0, 0.0, 0.0, 1.0)), Some(1.0));
+    commands.set_uniforms(view, projection);
+    commands.bind_texture(Rc::new(checker()), Sampler::default());
+    commands.draw_indexed(Rc::new(quad()), ground);
+
+    let (mut colour, mut depth) = surfaces();
+    commands.submit(&mut RenderTargets::new(&mut colour, &mut depth));
+
+    let bottom_row = (0..WIDTH).map(|i| colour.read(i, HEIGHT - 1));
+    assert!(bottom_row.into_iter().all(|texel| texel != 0xFF000000));
+
+    check_golden("large_ground_plane", &colour, Tolerance::default());
+}
+
+//A wall hiding one quad and half of another, with and without the depth pyramid
+#[test]
+fn hiz_occlusion() {
+    let camera = Camera { position: Vec3::new(0.0, 0.0, 2.0), euler_rotation: Vec3::ZERO, ..camera() };
+    let (view, projection) = camera.generate_view_projection();
+    let quad = Rc::new(quad());
+
+    //The quad sits at z 0.5, so these put the wall 1 unit from the camera and the others 3
+    let wall = Mat4::from_scale_rotation_translation(Vec3::new(1.3, 2.0, 1.0), Quat::IDENTITY, Vec3::new(-0.35, 0.0, 0.5));
+    let hidden = Mat4::from_translation(Vec3::new(-0.1, 0.0, -1.5));
+    let half_hidden = Mat4::from_translation(Vec3::new(0.8, 0.0, -1.5));
+
+    let mut commands = CommandBuffer::default();
+    commands.clear(Some(Vec4::new(0.0, 0.0, 0.0, 1.0)), Some(1.0));
+    commands.set_uniforms(view, projection);
+    commands.draw_indexed(quad.clone(), wall);
+    commands.bind_texture(Rc::new(checker()), Sampler::default());
+    commands.draw_indexed_instanced(quad, vec![InstanceData::from_model(hidden), InstanceData::from_model(half_hidden)]);
+
+    let (mut plain, mut plain_depth) = surfaces();
+    let plain_stats = commands.submit(&mut RenderTargets::new(&mut plain, &mut plain_depth));
+
+    let (mut colour, mut depth) = surfaces();
+    let mut hiz = HiZBuffer::new(WIDTH, HEIGHT);
+    let stats = commands.submit(&mut RenderTargets::new(&mut colour, &mut depth).with_hiz(&mut hiz));
+
+    assert_eq!((plain_stats.occluded_instances, stats.occluded_instances), (0, 1));
+    assert_eq!(plain.as_slice(), colour.as_slice());
+    assert_eq!(plain_depth.as_slice(), depth.as_slice());
+
+    check_golden("hiz_occlusion", &colour, Tolerance::default());
+}
+
+#[test]
+fn strips_fans_and_lines() {
+    let camera = Camera { position: Vec3::new(0.0, 0.0, 2.0), euler_rotation: Vec3::ZERO, ..camera() };
+    let (view, projection) = camera.generate_view_projection();
+
+    //Two rows of 2x4 vertices, drawn as two strips split by a restart
+    let grid: Vec<Vec3> = (0..2).flat_map(|row| (0..8).map(move |i| Vec3::new(-1.2 + (i / 2) as f32 * 0.25, 0.6 - row as f32 * 0.5 - (i % 2) as f32 * 0.3, 0.0))).collect();
+    let strip_vertices = VertexInput {
+        colours: grid.iter().map(|p| Vec3::new(p.x + 1.2, p.y + 0.5, 0.5)).collect(),
+        uvs: vec![Vec2::ZERO; grid.len()],
+        positions: grid,
+        ..Default::default()
+    };
+    let strips = Mesh::with_topology(strip_vertices, vec![0u16, 1, 2, 3, 4, 5, 6, 7, u16::MAX, 8, 9, 10, 11, 12, 13, 14, 15], Topology::TriangleStrip).with_primitive_restart();
+
+    let hexagon: Vec<Vec3> = std::iter::once(Vec3::new(0.5, 0.3, 0.0))
+        .chain((0..=6).map(|i| {
+            let angle = i as f32 / 6.0 * std::f32::consts::TAU;
+            Vec3::new(0.5 + angle.cos() * 0.4, 0.3 + angle.sin() * 0.4, 0.0)
+        }))
+        .collect();
+    let fan_vertices = VertexInput {
+        colours: (0..hexagon.len()).map(|i| if i == 0 { Vec3::ONE } else { Vec3::new(i as f32 / 6.0, 0.2, 1.0 - i as f32 / 6.0) }).collect(),
+        uvs: vec![Vec2::ZERO; hexagon.len()],
+        positions: hexagon,
+        ..Default::default()
+    };
+    let fan = Mesh::with_topology(fan_vertices, vec![0u32, 1, 2, 3, 4, 5, 6, 7], Topology::TriangleFan);
+
+    let zigzag: Vec<Vec3> = (0..7).map(|i| Vec3::new(-1.2 + i as f32 * 0.4, -0.6 + (i % 2) as f32 * 0.3, 0.0)).collect();
+    let line_vertices = VertexInput {
+        colours: zigzag.iter().map(|p| Vec3::new(1.0, p.x * 0.4 + 0.5, 0.0)).collect(),
+        uvs: vec![Vec2::ZERO; zigzag.len()],
+        positions: zigzag,
+        ..Default::default()
+    };
+    let line_strip = Rc::new(Mesh::with_topology(line_vertices, vec![0usize, 1, 2, 3, 4, 5, 6], Topology::LineStrip));
+
+    let thick_lines = PipelineState { raster: state::RasterState { line_width: 3, ..Default::default() }, ..Default::default() };
+
+    let mut commands = CommandBuffer::default();
+    commands.clear(Some(Vec4::new(0.0, 0.0, 0.0, 1.0)), Some(1.0));
+    commands.set_uniforms(view, projection);
+    commands.draw_indexed(Rc::new(strips), Mat4::IDENTITY);
+    commands.draw_indexed(Rc::new(fan), Mat4::IDENTITY);
+    commands.draw_indexed(line_strip.clone(), Mat4::IDENTITY);
+    commands.bind_pipeline(Rc::new(thick_lines));
+    commands.draw_indexed(line_strip, Mat4::from_translation(Vec3::new(0.0, -0.3, 0.0)));
+
+    let (mut colour, mut depth) = surfaces();
+    let stats = commands.submit(&mut RenderTargets::new(&mut colour, &mut depth));
+
+    //6 triangles per strip, 6 for the fan and 6 segments per line strip
+    assert_eq!(stats.input_primitives, 6 + 6 + 6 + 6 + 6);
+    assert_eq!(stats.rasterized_primitives, stats.input_primitives);
+
+    check_golden("strips_fans_and_lines", &colour, Tolerance::default());
+}